@@ -0,0 +1,181 @@
+use std::io;
+use std::process::Stdio;
+use std::sync::Arc;
+use std::sync::atomic::AtomicU64;
+use chrono::{DateTime, Utc};
+use serde_json::Value;
+use tokio::time::{timeout, Duration, Instant};
+use crate::benchmark::parser::{BenchmarkStats, RunLayout};
+use crate::benchmark::rusage::{self, ResourceReport, ResourceUsage};
+use crate::benchmark::test_runner::{
+    self, BenchmarkParameters, BenchmarkResult, BenchmarkRun, RunOptions, SUBMISSION_COMMAND,
+};
+use crate::utils::integrity::FileFingerprint;
+
+/// Timings the native harness took itself, each run being a fresh process.
+#[derive(Debug)]
+pub struct NativeBenchmark {
+    /// Wall times in seconds of the runs that were thrown away.
+    pub warmups: Vec<f64>,
+    /// Wall times in seconds of the runs that count.
+    pub values: Vec<f64>,
+    /// Usage of each run, warmups first.
+    pub runs: Vec<ResourceUsage>,
+    pub start_date: DateTime<Utc>,
+    pub end_date: DateTime<Utc>,
+}
+
+impl NativeBenchmark {
+    /// Every run together, warmups included.
+    pub fn usage(&self) -> ResourceUsage {
+        let mut total = ResourceUsage::default();
+        for run in &self.runs {
+            total.add(run);
+        }
+        total
+    }
+
+    /// The same statistics pyperf's results are turned into. The benchmark
+    /// counts as a single uncalibrated run of one loop, with every value its
+    /// own process.
+    pub fn stats(&self, test_run: Option<ResourceUsage>) -> BenchmarkStats {
+        let layout = RunLayout {
+            calibration_runs: 0,
+            value_runs: 1,
+            total_runs: 1,
+            warmups_per_run: self.warmups.len() as u32,
+            values_per_run: self.values.len(),
+            loop_iterations: 1,
+        };
+        let resource_usage = ResourceReport {
+            test_run,
+            benchmark: Some(self.usage()),
+        };
+        let parameters = BenchmarkParameters::Native {
+            warmups: self.warmups.len() as u32,
+            runs: self.values.len() as u32,
+        };
+        BenchmarkStats::from_values(&self.values, layout, self.start_date, self.end_date, resource_usage, parameters)
+    }
+
+    /// The raw timings, kept as the benchmark file in place of pyperf's.
+    pub fn raw(&self) -> Value {
+        serde_json::json!({
+            "harness": "native",
+            "metadata": {
+                "date": self.start_date.format("%Y-%m-%d %H:%M:%S%.3f").to_string(),
+                "duration": (self.end_date - self.start_date).num_milliseconds() as f64 / 1000.0,
+                "loops": 1,
+            },
+            "warmups": self.warmups,
+            "values": self.values,
+            "runs": self.runs,
+        })
+    }
+}
+
+/// Benchmarks `main.py` without pyperf: `warmups` runs that are thrown away,
+/// then `runs` that are timed, each in a fresh process under the same
+/// sandbox and limits as the test run. A run is timed from starting
+/// `main.py` until it exits, as pyperf's `command` measures it, from inside
+/// the sandbox if there is one so setting it up isn't counted.
+///
+/// A run that takes longer than `timeout_seconds`, or goes past what is left
+/// of `benchmark_budget_seconds`, stops the benchmark, and so does a run that
+/// changed the testcase at `testcase_path`. What the runs print on stderr
+/// shares a single `max_log_bytes` budget, as it does under pyperf.
+pub async fn run(
+    options: &RunOptions,
+    warmups: u32,
+    runs: u32,
+    testcase_path: &str,
+    testcase: &FileFingerprint,
+) -> io::Result<BenchmarkRun> {
+    println!("Running native benchmark: {} warmups, {} runs", warmups, runs);
+
+    let run_timeout = Duration::from_secs(options.timeout_seconds);
+    let deadline = Instant::now() + Duration::from_secs(options.benchmark_budget_seconds);
+    let start_date = Utc::now();
+    let mut benchmark = NativeBenchmark {
+        warmups: Vec::new(),
+        values: Vec::new(),
+        runs: Vec::new(),
+        start_date,
+        end_date: start_date,
+    };
+    let log_budget = Arc::new(AtomicU64::new(options.max_log_bytes));
+
+    for index in 0..warmups + runs {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        let (limit, reason) = if remaining < run_timeout {
+            (remaining, format!("Benchmark exceeded its budget of {} seconds", options.benchmark_budget_seconds))
+        } else {
+            (run_timeout, format!("A benchmark run timed out after {} seconds", options.timeout_seconds))
+        };
+
+        // The output was validated in the test run, so every run's is dropped
+        let mut run = test_runner::spawn_run(
+            &SUBMISSION_COMMAND,
+            options.sandbox.as_ref(),
+            options.limits,
+            Stdio::null(),
+            log_budget.clone(),
+        )?;
+        let process_group = run.process_group;
+
+        let (status, reaped, usage) = match timeout(limit, rusage::join_waiter(&mut run.waiter)).await {
+            Ok(exited) => exited?,
+            Err(_) => {
+                println!("{}, stopping the benchmark", reason);
+                run.confinement.terminate(process_group).await?;
+                let _ = rusage::join_waiter(&mut run.waiter).await;
+                return Ok(BenchmarkRun::Stopped { reason, values: benchmark.values });
+            }
+        };
+
+        test_runner::check_setup(run.report.as_ref())?;
+        let wall_time = run.runtime(reaped);
+        let stopped = run.confinement.terminate(process_group).await?;
+        if stopped > 0 {
+            println!("Stopped {} processes main.py left running", stopped);
+        }
+        if let Some(change) = testcase.changes(testcase_path) {
+            let reason = format!("Input modified during the benchmark: {}", change);
+            println!("{}, stopping the benchmark", reason);
+            return Ok(BenchmarkRun::Stopped { reason, values: benchmark.values });
+        }
+        let (logs_exceeded, log_tail) = match run.log_forwarder.as_mut() {
+            Some(forwarder) => {
+                let forwarded = test_runner::join_forwarder(forwarder).await?;
+                (forwarded.exceeded, forwarded.tail)
+            }
+            None => (false, Vec::new()),
+        };
+        if logs_exceeded {
+            return Err(io::Error::other(format!(
+                "Log output exceeded the limit of {} bytes during the benchmark",
+                options.max_log_bytes
+            )));
+        }
+        if let Some(limit) = run.confinement.exceeded(status, &log_tail) {
+            return Err(io::Error::other(limit.to_string()));
+        }
+        if !status.success() {
+            return Err(io::Error::other(format!("Benchmark run {} failed: {}", index + 1, status)));
+        }
+
+        let value = wall_time.as_secs_f64();
+        if index < warmups {
+            println!("Warmup {}: {:.3} ms", index + 1, value * 1000.0);
+            benchmark.warmups.push(value);
+        } else {
+            println!("Value {}: {:.3} ms", index - warmups + 1, value * 1000.0);
+            benchmark.values.push(value);
+        }
+        benchmark.runs.push(usage);
+    }
+
+    benchmark.end_date = Utc::now();
+    println!("Benchmark resource usage: {}", benchmark.usage().summary());
+    Ok(BenchmarkRun::Finished(BenchmarkResult::Native(benchmark)))
+}
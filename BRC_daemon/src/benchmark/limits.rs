@@ -0,0 +1,420 @@
+use std::ffi::CString;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::os::unix::process::ExitStatusExt;
+use std::path::{Path, PathBuf};
+use std::process::ExitStatus;
+use std::sync::{Mutex, OnceLock, PoisonError};
+use tokio::time::{sleep, Duration, Instant};
+
+const CGROUP_PREFIX: &str = "brc-daemon-";
+/// The leaf the daemon moves itself into, beside the runs' leaves.
+const DAEMON_CGROUP: &str = "daemon";
+/// Controllers the daemon enabled in the cgroup it started in, once it has
+/// moved itself into `DAEMON_CGROUP`. `restore_cgroup` undoes both.
+static ENABLED_CONTROLLERS: Mutex<Option<Vec<String>>> = Mutex::new(None);
+/// How long a run's processes get to exit after SIGTERM before they're killed.
+const TERMINATE_GRACE: Duration = Duration::from_secs(2);
+/// How long SIGKILL gets to take effect before survivors are reported.
+const KILL_GRACE: Duration = Duration::from_secs(1);
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+/// Caps on what one run of a submission may use, shared by everything it
+/// starts. `None` leaves a resource unlimited.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ResourceLimits {
+    pub max_memory_bytes: Option<u64>,
+    /// Processes and threads together.
+    pub max_processes: Option<u64>,
+    pub max_file_size_bytes: Option<u64>,
+    pub max_open_files: Option<u64>,
+}
+
+/// Which limit a run ran into.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LimitExceeded {
+    Memory(u64),
+    Processes(u64),
+    FileSize(u64),
+    OpenFiles(u64),
+}
+
+impl fmt::Display for LimitExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LimitExceeded::Memory(bytes) => write!(f, "Memory limit exceeded ({} bytes)", bytes),
+            LimitExceeded::Processes(count) => write!(f, "Process limit exceeded ({} processes and threads)", count),
+            LimitExceeded::FileSize(bytes) => write!(f, "File size limit exceeded ({} bytes)", bytes),
+            LimitExceeded::OpenFiles(count) => write!(f, "Open file limit exceeded ({} files)", count),
+        }
+    }
+}
+
+/// A cgroup v2 leaf holding a single run, so memory and process counts cover
+/// the whole tree rather than each process on its own, and nothing the run
+/// starts can slip out of it.
+#[derive(Debug)]
+struct Cgroup {
+    path: PathBuf,
+}
+
+impl Cgroup {
+    /// Creates a leaf under the cgroup the daemon started in with the limits
+    /// cgroups can enforce, or fails if the hierarchy doesn't delegate them to us.
+    fn create(limits: &ResourceLimits) -> io::Result<Cgroup> {
+        let controllers: Vec<&str> = [
+            limits.max_memory_bytes.map(|_| "memory"),
+            limits.max_processes.map(|_| "pids"),
+        ]
+        .into_iter()
+        .flatten()
+        .collect();
+
+        let parent = delegated_cgroup()?;
+        let enabled = fs::read_to_string(parent.join("cgroup.subtree_control"))?;
+        let missing: Vec<String> = controllers
+            .iter()
+            .filter(|controller| !enabled.split_whitespace().any(|enabled| enabled == **controller))
+            .map(|controller| format!("+{}", controller))
+            .collect();
+        if !missing.is_empty() {
+            // Controllers are only handed down by a cgroup without processes of its own
+            let mut enabled_controllers = ENABLED_CONTROLLERS.lock().unwrap_or_else(PoisonError::into_inner);
+            move_daemon(parent)?;
+            let enabled_controllers = enabled_controllers.get_or_insert_with(Vec::new);
+            fs::write(parent.join("cgroup.subtree_control"), missing.join(" "))
+                .map_err(|e| io::Error::new(e.kind(), format!("can't enable {} controllers: {}", missing.join(" "), e)))?;
+            enabled_controllers.extend(missing.iter().map(|controller| controller.trim_start_matches('+').to_string()));
+        }
+
+        let path = parent.join(format!("{}{}", CGROUP_PREFIX, uuid::Uuid::new_v4()));
+        fs::create_dir(&path)?;
+        let cgroup = Cgroup { path };
+
+        if let Some(bytes) = limits.max_memory_bytes {
+            cgroup.write("memory.max", &bytes.to_string())?;
+            // Swapping out would only turn the limit into a slowdown
+            cgroup.write("memory.swap.max", "0").unwrap_or_default();
+            cgroup.write("memory.oom.group", "1")?;
+        }
+        if let Some(count) = limits.max_processes {
+            cgroup.write("pids.max", &count.to_string())?;
+        }
+        Ok(cgroup)
+    }
+
+    fn write(&self, file: &str, value: &str) -> io::Result<()> {
+        fs::write(self.path.join(file), value)
+    }
+
+    fn processes(&self) -> Vec<libc::pid_t> {
+        fs::read_to_string(self.path.join("cgroup.procs"))
+            .unwrap_or_default()
+            .lines()
+            .filter_map(|pid| pid.parse().ok())
+            .collect()
+    }
+
+    /// Reads a counter from a flat-keyed file such as `memory.events`.
+    fn event(&self, file: &str, key: &str) -> u64 {
+        fs::read_to_string(self.path.join(file))
+            .unwrap_or_default()
+            .lines()
+            .find_map(|line| line.strip_prefix(key)?.trim().parse().ok())
+            .unwrap_or(0)
+    }
+}
+
+impl Drop for Cgroup {
+    fn drop(&mut self) {
+        fs::remove_dir(&self.path).unwrap_or_default();
+    }
+}
+
+/// Finds the cgroup v2 directory the daemon runs in.
+fn own_cgroup() -> io::Result<PathBuf> {
+    let membership = fs::read_to_string("/proc/self/cgroup")?;
+    let relative = membership
+        .lines()
+        .find_map(|line| line.strip_prefix("0::"))
+        .ok_or_else(|| io::Error::other("not in a cgroup v2 hierarchy"))?;
+
+    // Mount info lines are "<id> <parent> <dev> <root> <mount point> ... - <type> ..."
+    let mount_info = fs::read_to_string("/proc/self/mountinfo")?;
+    let mount_point = mount_info
+        .lines()
+        .find_map(|line| {
+            let (mount, filesystem) = line.split_once(" - ")?;
+            filesystem.starts_with("cgroup2 ").then(|| mount.split(' ').nth(4))?
+        })
+        .ok_or_else(|| io::Error::other("cgroup2 is not mounted"))?;
+
+    Ok(Path::new(mount_point).join(relative.trim_start_matches('/')))
+}
+
+/// The cgroup the daemon started in, which the runs' leaves go under. Found
+/// once, since the daemon moves out of it to enable controllers.
+fn delegated_cgroup() -> io::Result<&'static Path> {
+    static DELEGATED: OnceLock<Result<PathBuf, String>> = OnceLock::new();
+    DELEGATED
+        .get_or_init(|| own_cgroup().map_err(|e| e.to_string()))
+        .as_deref()
+        .map_err(|e| io::Error::other(e.to_string()))
+}
+
+/// Moves the daemon out of `parent` into a leaf of its own. Anything else
+/// left in `parent` still keeps controllers from being enabled there.
+fn move_daemon(parent: &Path) -> io::Result<()> {
+    let leaf = parent.join(DAEMON_CGROUP);
+    if let Err(e) = fs::create_dir(&leaf)
+        && e.kind() != io::ErrorKind::AlreadyExists
+    {
+        return Err(e);
+    }
+    fs::write(leaf.join("cgroup.procs"), std::process::id().to_string())
+        .map_err(|e| io::Error::new(e.kind(), format!("can't move the daemon into {}: {}", leaf.display(), e)))
+}
+
+/// Leaves the cgroup the daemon started in as it found it: disables the
+/// controllers the daemon enabled there, moves the daemon back and removes
+/// its leaf. Called at exit, once every run's leaf is gone.
+pub fn restore_cgroup() {
+    let Some(enabled) = ENABLED_CONTROLLERS.lock().unwrap_or_else(PoisonError::into_inner).take() else {
+        return;
+    };
+    let Ok(parent) = delegated_cgroup() else {
+        return;
+    };
+
+    let disable: Vec<String> = enabled.iter().map(|controller| format!("-{}", controller)).collect();
+    if !disable.is_empty()
+        && let Err(e) = fs::write(parent.join("cgroup.subtree_control"), disable.join(" "))
+    {
+        eprintln!("Failed to disable the {} controllers in {}: {}", enabled.join(" "), parent.display(), e);
+    }
+    if let Err(e) = fs::write(parent.join("cgroup.procs"), std::process::id().to_string()) {
+        eprintln!("Failed to move the daemon back to {}: {}", parent.display(), e);
+        return;
+    }
+    fs::remove_dir(parent.join(DAEMON_CGROUP)).unwrap_or_default();
+}
+
+/// Live processes in a process group, found by scanning `/proc`. Zombies
+/// are already dead and only wait to be reaped.
+fn group_processes(process_group: libc::pid_t) -> Vec<libc::pid_t> {
+    let Ok(entries) = fs::read_dir("/proc") else {
+        return Vec::new();
+    };
+    entries
+        .flatten()
+        .filter_map(|entry| entry.file_name().to_str()?.parse::<libc::pid_t>().ok())
+        .filter(|pid| {
+            // The fields after the parenthesized command are "<state> <ppid> <pgrp> ..."
+            let Ok(stat) = fs::read_to_string(format!("/proc/{}/stat", pid)) else {
+                return false;
+            };
+            let Some((_, fields)) = stat.rsplit_once(')') else {
+                return false;
+            };
+            let mut fields = fields.split_whitespace();
+            let state = fields.next();
+            let group = fields.nth(1).and_then(|group| group.parse::<libc::pid_t>().ok());
+            group == Some(process_group) && !matches!(state, Some("Z" | "X"))
+        })
+        .collect()
+}
+
+/// One run's processes and the limits on them. Every run leads its own
+/// process group, and also gets a cgroup when the hierarchy allows it;
+/// limits a cgroup can't enforce fall back to rlimits.
+#[derive(Debug)]
+pub struct Confinement {
+    limits: ResourceLimits,
+    cgroup: Option<Cgroup>,
+}
+
+impl Confinement {
+    pub fn new(limits: ResourceLimits) -> Confinement {
+        let cgroup = match Cgroup::create(&limits) {
+            Ok(cgroup) => Some(cgroup),
+            Err(e) => {
+                if limits.max_memory_bytes.is_some() || limits.max_processes.is_some() {
+                    println!("cgroup v2 limits unavailable ({}), falling back to rlimits", e);
+                }
+                None
+            }
+        };
+        Confinement { limits, cgroup }
+    }
+
+    /// Live processes belonging to the run led by `process_group`.
+    pub fn processes(&self, process_group: libc::pid_t) -> Vec<libc::pid_t> {
+        match &self.cgroup {
+            Some(cgroup) => cgroup.processes(),
+            None => group_processes(process_group),
+        }
+    }
+
+    fn signal(&self, process_group: libc::pid_t, signal: libc::c_int) {
+        if signal == libc::SIGKILL
+            && let Some(cgroup) = &self.cgroup
+            && cgroup.write("cgroup.kill", "1").is_ok()
+        {
+            return;
+        }
+        unsafe { libc::kill(-process_group, signal) };
+        for pid in self.processes(process_group) {
+            unsafe { libc::kill(pid, signal) };
+        }
+    }
+
+    async fn wait_for_exit(&self, process_group: libc::pid_t, grace: Duration) -> Vec<libc::pid_t> {
+        let deadline = Instant::now() + grace;
+        loop {
+            let remaining = self.processes(process_group);
+            if remaining.is_empty() || Instant::now() >= deadline {
+                return remaining;
+            }
+            sleep(POLL_INTERVAL).await;
+        }
+    }
+
+    /// Stops everything still running in the run led by `process_group`:
+    /// SIGTERM first, then SIGKILL for whatever outlives the grace period.
+    /// Returns how many processes had to be stopped, or an error naming any
+    /// that survived SIGKILL.
+    pub async fn terminate(&self, process_group: u32) -> io::Result<usize> {
+        let process_group = process_group as libc::pid_t;
+        let running = self.processes(process_group).len();
+        if running == 0 {
+            return Ok(0);
+        }
+
+        self.signal(process_group, libc::SIGTERM);
+        if self.wait_for_exit(process_group, TERMINATE_GRACE).await.is_empty() {
+            return Ok(running);
+        }
+
+        self.signal(process_group, libc::SIGKILL);
+        let survivors = self.wait_for_exit(process_group, KILL_GRACE).await;
+        if !survivors.is_empty() {
+            return Err(io::Error::other(format!(
+                "Processes {:?} are still running after SIGKILL",
+                survivors
+            )));
+        }
+        Ok(running)
+    }
+
+    /// Returns a hook for `pre_exec` that puts the child in a new process
+    /// group and the cgroup, and sets its rlimits. It runs between fork and
+    /// exec, so it only makes plain system calls on data prepared here.
+    pub fn pre_exec_hook(&self) -> io::Result<impl FnMut() -> io::Result<()> + Send + Sync + 'static> {
+        let procs = self
+            .cgroup
+            .as_ref()
+            .map(|cgroup| CString::new(cgroup.path.join("cgroup.procs").as_os_str().as_encoded_bytes()))
+            .transpose()
+            .map_err(io::Error::other)?;
+
+        // Without a cgroup, the address space and per-user process count are
+        // the closest rlimits. The latter counts all of the user's processes,
+        // not just this run's, unless the run is sandboxed
+        let mut rlimits = Vec::new();
+        if self.cgroup.is_none() {
+            rlimits.extend(self.limits.max_memory_bytes.map(|bytes| (libc::RLIMIT_AS, bytes)));
+            rlimits.extend(self.limits.max_processes.map(|count| (libc::RLIMIT_NPROC, count)));
+        }
+        rlimits.extend(self.limits.max_file_size_bytes.map(|bytes| (libc::RLIMIT_FSIZE, bytes)));
+        rlimits.extend(self.limits.max_open_files.map(|count| (libc::RLIMIT_NOFILE, count)));
+
+        Ok(move || {
+            if unsafe { libc::setpgid(0, 0) } == -1 {
+                return Err(io::Error::last_os_error());
+            }
+            if let Some(procs) = &procs {
+                let fd = unsafe { libc::open(procs.as_ptr(), libc::O_WRONLY | libc::O_CLOEXEC) };
+                if fd == -1 {
+                    return Err(io::Error::last_os_error());
+                }
+                // Writing 0 moves the writing process
+                let written = unsafe { libc::write(fd, c"0".as_ptr().cast(), 1) };
+                unsafe { libc::close(fd) };
+                if written != 1 {
+                    return Err(io::Error::last_os_error());
+                }
+            }
+            for &(resource, value) in &rlimits {
+                let limit = libc::rlimit {
+                    rlim_cur: value,
+                    rlim_max: value,
+                };
+                if unsafe { libc::setrlimit(resource, &limit) } == -1 {
+                    return Err(io::Error::last_os_error());
+                }
+            }
+            Ok(())
+        })
+    }
+
+    /// Works out whether a failed run was stopped by one of the limits, from
+    /// the cgroup's event counters, the exit signal and the end of stderr.
+    pub fn exceeded(&self, status: ExitStatus, stderr_tail: &[u8]) -> Option<LimitExceeded> {
+        if status.success() {
+            return None;
+        }
+
+        let limits = &self.limits;
+        if let Some(cgroup) = &self.cgroup {
+            if let Some(bytes) = limits.max_memory_bytes
+                && cgroup.event("memory.events", "oom_kill ") > 0
+            {
+                return Some(LimitExceeded::Memory(bytes));
+            }
+            if let Some(count) = limits.max_processes
+                && cgroup.event("pids.events", "max ") > 0
+            {
+                return Some(LimitExceeded::Processes(count));
+            }
+        }
+        if let Some(bytes) = limits.max_file_size_bytes
+            && status.signal() == Some(libc::SIGXFSZ)
+        {
+            return Some(LimitExceeded::FileSize(bytes));
+        }
+
+        // Python turns most limits into exceptions rather than signals
+        let stderr = String::from_utf8_lossy(stderr_tail);
+        let mentions = |patterns: &[&str]| patterns.iter().any(|pattern| stderr.contains(pattern));
+        if let Some(bytes) = limits.max_memory_bytes
+            && mentions(&["MemoryError", "Cannot allocate memory"])
+        {
+            return Some(LimitExceeded::Memory(bytes));
+        }
+        // EAGAIN on its own could come from any non-blocking call, so it only
+        // counts when a fork failed with it, on the same line or the
+        // traceback line before it
+        let lines: Vec<&str> = stderr.lines().collect();
+        let fork_failed = lines.iter().enumerate().any(|(i, line)| {
+            line.contains("Resource temporarily unavailable")
+                && lines[i.saturating_sub(1)..=i].iter().any(|line| line.contains("fork"))
+        });
+        if let Some(count) = limits.max_processes
+            && (fork_failed || mentions(&["can't start new thread"]))
+        {
+            return Some(LimitExceeded::Processes(count));
+        }
+        if let Some(bytes) = limits.max_file_size_bytes
+            && mentions(&["File too large"])
+        {
+            return Some(LimitExceeded::FileSize(bytes));
+        }
+        if let Some(count) = limits.max_open_files
+            && mentions(&["Too many open files"])
+        {
+            return Some(LimitExceeded::OpenFiles(count));
+        }
+        None
+    }
+}
@@ -1,2 +1,6 @@
+pub mod harness;
+pub mod limits;
 pub mod parser;
+pub mod rusage;
+pub mod sandbox;
 pub mod test_runner;
\ No newline at end of file
@@ -2,6 +2,8 @@ use chrono::{DateTime, NaiveDateTime, Utc};
 use serde::{Deserialize, Serialize};
 use serde_json::{Result, Value};
 use std::collections::HashMap;
+use crate::benchmark::rusage::ResourceReport;
+use crate::benchmark::test_runner::BenchmarkParameters;
 
 #[derive(Debug, Serialize)]
 pub struct BenchmarkStats {
@@ -25,12 +27,100 @@ pub struct BenchmarkStats {
     maximum: f64,
     percentiles: HashMap<String, f64>,
     outliers: usize,
+    resource_usage: ResourceReport,
+    parameters: BenchmarkParameters,
+}
+
+/// How a benchmark's values were collected, in pyperf's terms: runs are
+/// processes, each with its warmups and values.
+#[derive(Debug, Clone, Copy)]
+pub struct RunLayout {
+    pub calibration_runs: u32,
+    pub value_runs: u32,
+    pub total_runs: u32,
+    pub warmups_per_run: u32,
+    pub values_per_run: usize,
+    pub loop_iterations: u32,
 }
 
 impl BenchmarkStats {
     pub fn get_mean(&self) -> f64 {
         self.mean
     }
+
+    /// Computes the statistics over `values`, one per benchmark value in
+    /// seconds, collected the way `layout` describes.
+    pub fn from_values(
+        values: &[f64],
+        layout: RunLayout,
+        start_date: DateTime<Utc>,
+        end_date: DateTime<Utc>,
+        resource_usage: ResourceReport,
+        parameters: BenchmarkParameters,
+    ) -> BenchmarkStats {
+        let values_ms: Vec<f64> = values.iter().map(|&v| v * 1_000_000.0).collect();
+        let total_duration = (end_date - start_date).num_seconds() as f64;
+
+        let median_value = median(&values_ms);
+        let deviations: Vec<f64> = values_ms
+            .iter()
+            .map(|&x| (x - median_value).abs())
+            .collect();
+        let mad = median(&deviations);
+        let mean_value = mean(&values_ms);
+        let stddev_value = stddev(&values_ms);
+
+        let mut percentiles = HashMap::new();
+        percentiles.insert("0th".to_string(), percentile(&values_ms, 0.0));
+        percentiles.insert("5th".to_string(), percentile(&values_ms, 0.05));
+        percentiles.insert("25th".to_string(), percentile(&values_ms, 0.25));
+        percentiles.insert("50th".to_string(), percentile(&values_ms, 0.5));
+        percentiles.insert("75th".to_string(), percentile(&values_ms, 0.75));
+        percentiles.insert("95th".to_string(), percentile(&values_ms, 0.95));
+        percentiles.insert("100th".to_string(), percentile(&values_ms, 1.0));
+
+        let q1 = percentile(&values_ms, 0.25);
+        let q3 = percentile(&values_ms, 0.75);
+        let iqr = q3 - q1;
+        let lower_bound = q1 - 1.5 * iqr;
+        let upper_bound = q3 + 1.5 * iqr;
+        let outliers = values_ms
+            .iter()
+            .filter(|&&x| x < lower_bound || x > upper_bound)
+            .count();
+
+        BenchmarkStats {
+            total_duration,
+            start_date,
+            end_date,
+            // Convert to microseconds
+            raw_min: values.iter().copied().fold(f64::INFINITY, f64::min) * 1_000_000.0,
+            raw_max: values.iter().copied().fold(f64::NEG_INFINITY, f64::max) * 1_000_000.0,
+            calibration_runs: layout.calibration_runs,
+            value_runs: layout.value_runs,
+            total_runs: layout.total_runs,
+            warmups_per_run: layout.warmups_per_run,
+            values_per_run: layout.values_per_run,
+            loop_iterations: layout.loop_iterations,
+            total_values: values_ms.len(),
+            minimum: *values_ms
+                .iter()
+                .min_by(|a, b| a.partial_cmp(b).unwrap())
+                .unwrap_or(&0.0),
+            median: median_value,
+            mad,
+            mean: mean_value,
+            stddev: stddev_value,
+            maximum: *values_ms
+                .iter()
+                .max_by(|a, b| a.partial_cmp(b).unwrap())
+                .unwrap_or(&0.0),
+            percentiles,
+            outliers,
+            resource_usage,
+            parameters,
+        }
+    }
 }
 
 #[derive(Debug, Deserialize)]
@@ -54,7 +144,7 @@ fn median(values: &[f64]) -> f64 {
     sorted.sort_by(|a, b| a.partial_cmp(b).unwrap());
     let mid = sorted.len() / 2;
 
-    if sorted.len() % 2 != 0 {
+    if !sorted.len().is_multiple_of(2) {
         sorted[mid]
     } else {
         (sorted[mid - 1] + sorted[mid]) / 2.0
@@ -93,6 +183,8 @@ fn percentile(values: &[f64], p: f64) -> f64 {
 pub fn parse(
     data: serde_json::Value,
     skipped_calibration: bool,
+    resource_usage: ResourceReport,
+    parameters: BenchmarkParameters,
 ) -> Result<(BenchmarkStats, Value)> {
     let benchmark: &Value = &data["benchmarks"][0];
     let runs: Vec<Run> = serde_json::from_value(benchmark["runs"].clone())?;
@@ -116,8 +208,6 @@ pub fn parse(
         }
     }
 
-    let values_ms: Vec<f64> = all_values.iter().map(|&v| v * 1_000_000.0).collect();
-
     // Handle dates differently based on whether calibration was skipped
     let (start_date, end_date) = if skipped_calibration {
         // When calibration is skipped, use metadata from the top-level object
@@ -164,36 +254,6 @@ pub fn parse(
         (start, end)
     };
 
-    let total_duration = (end_date - start_date).num_seconds() as f64;
-
-    let median_value = median(&values_ms);
-    let deviations: Vec<f64> = values_ms
-        .iter()
-        .map(|&x| (x - median_value).abs())
-        .collect();
-    let mad = median(&deviations);
-    let mean_value = mean(&values_ms);
-    let stddev_value = stddev(&values_ms);
-
-    let mut percentiles = HashMap::new();
-    percentiles.insert("0th".to_string(), percentile(&values_ms, 0.0));
-    percentiles.insert("5th".to_string(), percentile(&values_ms, 0.05));
-    percentiles.insert("25th".to_string(), percentile(&values_ms, 0.25));
-    percentiles.insert("50th".to_string(), percentile(&values_ms, 0.5));
-    percentiles.insert("75th".to_string(), percentile(&values_ms, 0.75));
-    percentiles.insert("95th".to_string(), percentile(&values_ms, 0.95));
-    percentiles.insert("100th".to_string(), percentile(&values_ms, 1.0));
-
-    let q1 = percentile(&values_ms, 0.25);
-    let q3 = percentile(&values_ms, 0.75);
-    let iqr = q3 - q1;
-    let lower_bound = q1 - 1.5 * iqr;
-    let upper_bound = q3 + 1.5 * iqr;
-    let outliers = values_ms
-        .iter()
-        .filter(|&&x| x < lower_bound || x > upper_bound)
-        .count();
-
     // Get loop iterations - handling both cases safely
     let loop_iterations = if !runs.is_empty() && !runs[0].warmups.is_empty() {
         runs[0].warmups[0].0 as u32
@@ -205,9 +265,7 @@ pub fn parse(
     let warmups_per_run = if skipped_calibration {
         runs.first().map(|r| r.warmups.len() as u32).unwrap_or(0)
     } else {
-        runs.iter()
-            .skip(1)
-            .next()
+        runs.get(1)
             .map(|r| r.warmups.len() as u32)
             .unwrap_or(0)
     };
@@ -217,20 +275,12 @@ pub fn parse(
             .and_then(|r| r.values.as_ref().map(|v| v.len()))
             .unwrap_or(0)
     } else {
-        runs.iter()
-            .skip(1)
-            .next()
+        runs.get(1)
             .and_then(|r| r.values.as_ref().map(|v| v.len()))
             .unwrap_or(0)
     };
 
-    let stats = BenchmarkStats {
-        total_duration,
-        start_date,
-        end_date,
-        // Convert to microseconds
-        raw_min: all_values.iter().copied().fold(f64::INFINITY, f64::min) * 1_000_000.0,
-        raw_max: all_values.iter().copied().fold(f64::NEG_INFINITY, f64::max) * 1_000_000.0,
+    let layout = RunLayout {
         calibration_runs: if skipped_calibration {
             0
         } else {
@@ -245,22 +295,8 @@ pub fn parse(
         warmups_per_run,
         values_per_run,
         loop_iterations,
-        total_values: values_ms.len(),
-        minimum: *values_ms
-            .iter()
-            .min_by(|a, b| a.partial_cmp(b).unwrap())
-            .unwrap_or(&0.0),
-        median: median_value,
-        mad,
-        mean: mean_value,
-        stddev: stddev_value,
-        maximum: *values_ms
-            .iter()
-            .max_by(|a, b| a.partial_cmp(b).unwrap())
-            .unwrap_or(&0.0),
-        percentiles,
-        outliers,
     };
 
+    let stats = BenchmarkStats::from_values(&all_values, layout, start_date, end_date, resource_usage, parameters);
     Ok((stats, data))
 }
@@ -0,0 +1,118 @@
+use std::io;
+use std::os::unix::process::ExitStatusExt;
+use std::process::ExitStatus;
+use serde::Serialize;
+use tokio::task::JoinHandle;
+use tokio::time::{Duration, Instant};
+
+/// What a run used, from the kernel's accounting when it was reaped. It
+/// covers the process and every descendant it waited for; peak RSS is that
+/// of the largest single process.
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct ResourceUsage {
+    pub wall_seconds: f64,
+    pub user_cpu_seconds: f64,
+    pub system_cpu_seconds: f64,
+    /// User CPU time over wall time. Above 1 the run kept more than one core
+    /// busy, which is the point of a no-GIL solution.
+    pub parallelism: f64,
+    pub peak_rss_bytes: u64,
+    pub voluntary_context_switches: u64,
+    pub involuntary_context_switches: u64,
+    pub block_reads: u64,
+    pub block_writes: u64,
+}
+
+impl ResourceUsage {
+    fn from_rusage(usage: &libc::rusage, wall_seconds: f64) -> Self {
+        let seconds = |time: libc::timeval| time.tv_sec as f64 + time.tv_usec as f64 / 1_000_000.0;
+        let user_cpu_seconds = seconds(usage.ru_utime);
+        ResourceUsage {
+            wall_seconds,
+            user_cpu_seconds,
+            system_cpu_seconds: seconds(usage.ru_stime),
+            parallelism: if wall_seconds > 0.0 { user_cpu_seconds / wall_seconds } else { 0.0 },
+            // Linux reports it in kilobytes
+            peak_rss_bytes: usage.ru_maxrss as u64 * 1024,
+            voluntary_context_switches: usage.ru_nvcsw as u64,
+            involuntary_context_switches: usage.ru_nivcsw as u64,
+            block_reads: usage.ru_inblock as u64,
+            block_writes: usage.ru_oublock as u64,
+        }
+    }
+
+    /// User and system CPU time together.
+    pub fn cpu_time(&self) -> Duration {
+        Duration::from_secs_f64(self.user_cpu_seconds + self.system_cpu_seconds)
+    }
+
+    /// Adds the usage of a run that came after this one.
+    pub fn add(&mut self, other: &ResourceUsage) {
+        self.wall_seconds += other.wall_seconds;
+        self.user_cpu_seconds += other.user_cpu_seconds;
+        self.system_cpu_seconds += other.system_cpu_seconds;
+        self.parallelism = if self.wall_seconds > 0.0 { self.user_cpu_seconds / self.wall_seconds } else { 0.0 };
+        self.peak_rss_bytes = self.peak_rss_bytes.max(other.peak_rss_bytes);
+        self.voluntary_context_switches += other.voluntary_context_switches;
+        self.involuntary_context_switches += other.involuntary_context_switches;
+        self.block_reads += other.block_reads;
+        self.block_writes += other.block_writes;
+    }
+
+    pub fn summary(&self) -> String {
+        format!(
+            "user {:.3}s, system {:.3}s, parallelism {:.2}, peak RSS {:.1} MiB",
+            self.user_cpu_seconds,
+            self.system_cpu_seconds,
+            self.parallelism,
+            self.peak_rss_bytes as f64 / (1024.0 * 1024.0)
+        )
+    }
+}
+
+/// Resource usage reported next to the benchmark's timings.
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct ResourceReport {
+    /// The unbenchmarked run on its own.
+    pub test_run: Option<ResourceUsage>,
+    /// The whole benchmark: every run, and pyperf's own processes when it
+    /// drove them.
+    pub benchmark: Option<ResourceUsage>,
+}
+
+/// How a run ended: its exit status, wall time and resource usage.
+pub type Exited = (ExitStatus, Duration, ResourceUsage);
+pub type Waiter = JoinHandle<io::Result<Exited>>;
+
+/// Reaps `pid` with `wait4` on a blocking thread, so its resource usage
+/// isn't lost to whoever else would reap it. The process must have been
+/// started with `std::process::Command`, which never reaps on its own.
+/// Wall time runs from `started` until the process is reaped.
+pub fn wait_with_usage(pid: u32, started: Instant) -> Waiter {
+    tokio::task::spawn_blocking(move || {
+        let mut status = 0;
+        let mut usage: libc::rusage = unsafe { std::mem::zeroed() };
+        loop {
+            let result = unsafe { libc::wait4(pid as libc::pid_t, &mut status, 0, &mut usage) };
+            if result != -1 {
+                break;
+            }
+            let error = io::Error::last_os_error();
+            if error.kind() != io::ErrorKind::Interrupted {
+                return Err(error);
+            }
+        }
+        let wall_time = started.elapsed();
+        Ok((
+            ExitStatus::from_raw(status),
+            wall_time,
+            ResourceUsage::from_rusage(&usage, wall_time.as_secs_f64()),
+        ))
+    })
+}
+
+pub async fn join_waiter(waiter: &mut Waiter) -> io::Result<Exited> {
+    waiter
+        .await
+        .map_err(|e| io::Error::other(format!("Failed to wait for process: {}", e)))?
+}
@@ -0,0 +1,487 @@
+use std::ffi::CString;
+use std::fs;
+use std::io::{self, Write};
+use std::os::fd::{AsRawFd, RawFd};
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::{symlink, OpenOptionsExt};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use crate::utils::results;
+
+/// Hidden subcommand the daemon re-executes itself with to enter the sandbox.
+/// It has to run before the async runtime starts, since a multithreaded
+/// process can't create a user namespace.
+pub const SANDBOX_COMMAND: &str = "__sandbox";
+
+/// Host directories exposed read-only so the interpreter and its libraries
+/// keep working. Missing ones are skipped.
+const SYSTEM_PATHS: [&str; 8] = ["/usr", "/bin", "/sbin", "/lib", "/lib32", "/lib64", "/etc", "/opt"];
+const DEVICES: [&str; 5] = ["null", "zero", "full", "random", "urandom"];
+/// The submission runs as this unprivileged user inside the sandbox, so it
+/// holds no capabilities once it has been executed.
+const SANDBOX_ID: u32 = 1000;
+const SCRATCH_OPTIONS: &str = "mode=1777,size=512m";
+/// Exit code when the sandbox itself couldn't be set up. The daemon learns
+/// why from the sandbox report, since the code alone looks like the submission's.
+const SETUP_FAILED: i32 = 125;
+
+/// An isolated view of the machine for `main.py`: new user, mount, network
+/// and PID namespaces, with system directories read-only, `workdir` writable,
+/// the testcase read-only, a private `/tmp` and no network. Paths keep their
+/// host locations, so absolute paths mean the same thing on both sides.
+#[derive(Debug, Clone)]
+pub struct Sandbox {
+    /// Absolute directory the submission runs in, writable inside the sandbox.
+    pub workdir: PathBuf,
+    /// Files the submission can read but not change.
+    pub read_only_files: Vec<PathBuf>,
+    /// Extra host paths to expose read-only, e.g. an interpreter outside `/usr`.
+    pub read_only_paths: Vec<PathBuf>,
+}
+
+impl Sandbox {
+    /// Turns `command` into one that re-executes the daemon to set up the
+    /// sandbox and then runs `command` inside it, reporting back to `report`.
+    pub fn wrap(&self, command: &[&str], report: &SandboxReport) -> io::Result<Vec<String>> {
+        let daemon = std::env::current_exe()?;
+        let mut wrapped = vec![
+            daemon.to_string_lossy().into_owned(),
+            SANDBOX_COMMAND.to_string(),
+            "--status-file".to_string(),
+            report.status.to_string_lossy().into_owned(),
+        ];
+        if let Some(runtime) = &report.runtime {
+            wrapped.push("--runtime-file".to_string());
+            wrapped.push(runtime.to_string_lossy().into_owned());
+        }
+        wrapped.push("--workdir".to_string());
+        wrapped.push(self.workdir.to_string_lossy().into_owned());
+        for file in &self.read_only_files {
+            wrapped.push("--read-only-file".to_string());
+            wrapped.push(file.to_string_lossy().into_owned());
+        }
+        for path in &self.read_only_paths {
+            wrapped.push("--read-only".to_string());
+            wrapped.push(path.to_string_lossy().into_owned());
+        }
+        wrapped.push("--".to_string());
+        wrapped.extend(command.iter().map(|arg| arg.to_string()));
+        Ok(wrapped)
+    }
+
+    /// Writes an executable script to `path` that runs `command` with the
+    /// script's own arguments appended, inside the sandbox. pyperf takes it
+    /// as the interpreter for its workers, so only they run sandboxed.
+    pub fn launcher(&self, path: &Path, command: &[&str], report: &SandboxReport) -> io::Result<()> {
+        let quoted: Vec<String> = self
+            .wrap(command, report)?
+            .iter()
+            .map(|arg| format!("'{}'", arg.replace('\'', r"'\''")))
+            .collect();
+        fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .mode(0o700)
+            .open(path)?
+            .write_all(format!("#!/bin/sh\nexec {} \"$@\"\n", quoted.join(" ")).as_bytes())
+    }
+}
+
+/// Files in the daemon's private directory the sandbox reports back to. Setup
+/// failures go to one, so they surface as daemon errors rather than as the
+/// submission failing with `SETUP_FAILED`. Unlike an inherited descriptor, it
+/// also reaches the sandboxes pyperf starts its workers in. When timed, the
+/// other gets how long the command ran, measured from inside the sandbox.
+pub struct SandboxReport {
+    status: PathBuf,
+    runtime: Option<PathBuf>,
+}
+
+impl SandboxReport {
+    pub fn new(timed: bool) -> io::Result<Self> {
+        let id = uuid::Uuid::new_v4();
+        let status = results::private_path(&format!("sandbox-{}.log", id));
+        fs::File::create(&status)?;
+        let runtime = if timed {
+            let runtime = results::private_path(&format!("sandbox-{}.runtime", id));
+            fs::File::create(&runtime)?;
+            Some(runtime)
+        } else {
+            None
+        };
+        Ok(SandboxReport { status, runtime })
+    }
+
+    /// How long the command ran, from just before it was started until it
+    /// was reaped, without setting up the sandbox. Only there once the
+    /// sandbox has exited, and only if timed.
+    pub fn runtime(&self) -> Option<Duration> {
+        let nanos = fs::read_to_string(self.runtime.as_ref()?).ok()?.trim().parse().ok()?;
+        Some(Duration::from_nanos(nanos))
+    }
+
+    /// Why the sandbox couldn't be set up, if it couldn't. Only meaningful
+    /// once every sandboxed process has exited.
+    pub fn failure(&self) -> Option<String> {
+        let report = fs::read_to_string(&self.status).unwrap_or_default();
+        let report = report.trim().to_string();
+        (!report.is_empty()).then_some(report)
+    }
+}
+
+impl Drop for SandboxReport {
+    fn drop(&mut self) {
+        fs::remove_file(&self.status).unwrap_or_default();
+        if let Some(runtime) = &self.runtime {
+            fs::remove_file(runtime).unwrap_or_default();
+        }
+    }
+}
+
+/// The report files `run` was given, opened close-on-exec, so the sandbox's
+/// own processes keep them and the command doesn't.
+#[derive(Default)]
+struct ReportFiles {
+    status: Option<fs::File>,
+    runtime: Option<fs::File>,
+}
+
+/// Reports a setup failure on stderr and, when the daemon passed one, the status file.
+fn report_failure(status_fd: Option<RawFd>, error: &io::Error) {
+    let message = format!("Failed to set up the sandbox: {}", error);
+    eprintln!("{}", message);
+    if let Some(fd) = status_fd {
+        unsafe { libc::write(fd, message.as_ptr().cast(), message.len()) };
+    }
+}
+
+/// Entry point of the `__sandbox` subcommand. Runs the command after `--`
+/// inside the sandbox and exits the way it did.
+pub fn run(args: &[String]) -> ! {
+    let (reports, parsed) = parse_args(args);
+    let status_fd = reports.status.as_ref().map(AsRawFd::as_raw_fd);
+    match parsed.and_then(|(sandbox, command)| enter(&sandbox, &command, &reports)) {
+        Ok(code) => std::process::exit(code),
+        Err(e) => {
+            report_failure(status_fd, &e);
+            std::process::exit(SETUP_FAILED);
+        }
+    }
+}
+
+/// Parses the subcommand's arguments. The report files come first and are
+/// opened even when the rest is invalid, so that can be reported too.
+fn parse_args(mut args: &[String]) -> (ReportFiles, io::Result<(Sandbox, Vec<String>)>) {
+    let open = |path: &String| fs::OpenOptions::new().append(true).open(path).ok();
+    let mut reports = ReportFiles::default();
+    loop {
+        match args {
+            [flag, path, rest @ ..] if flag == "--status-file" => {
+                reports.status = open(path);
+                args = rest;
+            }
+            [flag, path, rest @ ..] if flag == "--runtime-file" => {
+                reports.runtime = open(path);
+                args = rest;
+            }
+            _ => return (reports, parse_sandbox_args(args)),
+        }
+    }
+}
+
+fn parse_sandbox_args(args: &[String]) -> io::Result<(Sandbox, Vec<String>)> {
+    let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidInput, message.to_string());
+    let mut sandbox = Sandbox {
+        workdir: PathBuf::new(),
+        read_only_files: Vec::new(),
+        read_only_paths: Vec::new(),
+    };
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--" => {
+                let command: Vec<String> = args.cloned().collect();
+                if command.is_empty() || !sandbox.workdir.is_absolute() {
+                    return Err(invalid("expected --workdir <absolute path> and a command after --"));
+                }
+                return Ok((sandbox, command));
+            }
+            "--workdir" => sandbox.workdir = args.next().ok_or_else(|| invalid("--workdir needs a path"))?.into(),
+            "--read-only-file" => sandbox
+                .read_only_files
+                .push(args.next().ok_or_else(|| invalid("--read-only-file needs a path"))?.into()),
+            "--read-only" => sandbox
+                .read_only_paths
+                .push(args.next().ok_or_else(|| invalid("--read-only needs a path"))?.into()),
+            other => return Err(invalid(&format!("unknown sandbox argument '{}'", other))),
+        }
+    }
+
+    Err(invalid("missing -- before the sandboxed command"))
+}
+
+fn check(result: libc::c_int, what: &str) -> io::Result<libc::c_int> {
+    if result == -1 {
+        let error = io::Error::last_os_error();
+        return Err(io::Error::new(error.kind(), format!("{}: {}", what, error)));
+    }
+    Ok(result)
+}
+
+fn c_path(path: &Path) -> io::Result<CString> {
+    CString::new(path.as_os_str().as_bytes()).map_err(io::Error::other)
+}
+
+fn mount(
+    source: Option<&Path>,
+    target: &Path,
+    fstype: Option<&str>,
+    flags: libc::c_ulong,
+    data: Option<&str>,
+) -> io::Result<()> {
+    let source = source.map(c_path).transpose()?;
+    let target_c = c_path(target)?;
+    let fstype = fstype.map(CString::new).transpose().map_err(io::Error::other)?;
+    let data = data.map(CString::new).transpose().map_err(io::Error::other)?;
+
+    let result = unsafe {
+        libc::mount(
+            source.as_ref().map_or(std::ptr::null(), |s| s.as_ptr()),
+            target_c.as_ptr(),
+            fstype.as_ref().map_or(std::ptr::null(), |s| s.as_ptr()),
+            flags,
+            data.as_ref().map_or(std::ptr::null(), |s| s.as_ptr().cast()),
+        )
+    };
+    check(result, &format!("mount {}", target.display())).map(|_| ())
+}
+
+/// Bind-mounts `source` onto `target`. A read-only remount inside a user
+/// namespace has to keep the flags the original mount is locked with.
+fn bind(source: &Path, target: &Path, read_only: bool) -> io::Result<()> {
+    mount(Some(source), target, None, libc::MS_BIND | libc::MS_REC, None)?;
+    if !read_only {
+        return Ok(());
+    }
+
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    check(unsafe { libc::statvfs(c_path(target)?.as_ptr(), &mut stat) }, "statvfs")?;
+    let locked = [
+        (libc::ST_NOSUID, libc::MS_NOSUID),
+        (libc::ST_NODEV, libc::MS_NODEV),
+        (libc::ST_NOEXEC, libc::MS_NOEXEC),
+        (libc::ST_NOATIME, libc::MS_NOATIME),
+        (libc::ST_NODIRATIME, libc::MS_NODIRATIME),
+        (libc::ST_RELATIME, libc::MS_RELATIME),
+    ]
+    .into_iter()
+    .filter(|(st_flag, _)| stat.f_flag & st_flag != 0)
+    .fold(0, |flags, (_, ms_flag)| flags | ms_flag);
+
+    mount(None, target, None, libc::MS_BIND | libc::MS_REMOUNT | libc::MS_RDONLY | locked, None)
+}
+
+fn inside(root: &Path, path: &Path) -> PathBuf {
+    root.join(path.strip_prefix("/").unwrap_or(path))
+}
+
+/// Makes `path` from the host appear at the same place under `root`.
+fn expose(root: &Path, path: &Path, read_only: bool) -> io::Result<()> {
+    let Ok(metadata) = fs::symlink_metadata(path) else {
+        return Ok(());
+    };
+    let target = inside(root, path);
+    if let Some(parent) = target.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    if metadata.file_type().is_symlink() {
+        // Merged /usr layouts link /bin and friends into /usr, which is exposed anyway
+        return symlink(fs::read_link(path)?, &target);
+    }
+    if metadata.is_dir() {
+        fs::create_dir_all(&target)?;
+    } else if !target.exists() {
+        fs::write(&target, [])?;
+    }
+    bind(path, &target, read_only)
+}
+
+/// Creates the namespaces and forks the sandbox's init process, then waits
+/// for it. Runs in the original mount namespace so it can clean up after.
+fn enter(sandbox: &Sandbox, command: &[String], reports: &ReportFiles) -> io::Result<i32> {
+    let uid = unsafe { libc::getuid() };
+    let gid = unsafe { libc::getgid() };
+    let root = std::env::temp_dir().join(format!("brc-sandbox-{}", uuid::Uuid::new_v4()));
+    fs::create_dir(&root)?;
+
+    check(unsafe { libc::unshare(libc::CLONE_NEWUSER | libc::CLONE_NEWPID) }, "unshare user and PID namespaces")?;
+    fs::write("/proc/self/setgroups", "deny")?;
+    fs::write("/proc/self/uid_map", format!("{} {} 1", SANDBOX_ID, uid))?;
+    fs::write("/proc/self/gid_map", format!("{} {} 1", SANDBOX_ID, gid))?;
+
+    // A SIGTERM to the run's process group is meant for the submission. This
+    // process stays until init exits, so the daemon sees how the run ended
+    unsafe { libc::signal(libc::SIGTERM, libc::SIG_IGN) };
+    let pid = check(unsafe { libc::fork() }, "fork")?;
+    if pid == 0 {
+        unsafe { libc::signal(libc::SIGTERM, libc::SIG_DFL) };
+        let code = match init(sandbox, &root, command, reports.runtime.as_ref()) {
+            Ok(code) => code,
+            Err(e) => {
+                report_failure(reports.status.as_ref().map(AsRawFd::as_raw_fd), &e);
+                SETUP_FAILED
+            }
+        };
+        unsafe { libc::_exit(code) };
+    }
+
+    let status = wait_for(pid)?;
+    fs::remove_dir(&root).unwrap_or_default();
+
+    // Re-raise a fatal signal so the daemon sees how the submission died
+    if libc::WIFEXITED(status) {
+        let code = libc::WEXITSTATUS(status);
+        if code > 128 && code <= 128 + 64 {
+            unsafe {
+                libc::signal(code - 128, libc::SIG_DFL);
+                libc::raise(code - 128);
+            }
+        }
+        return Ok(code);
+    }
+    Ok(128 + libc::WTERMSIG(status))
+}
+
+fn wait_for(pid: libc::pid_t) -> io::Result<libc::c_int> {
+    let mut status = 0;
+    loop {
+        let result = unsafe { libc::waitpid(pid, &mut status, 0) };
+        if result == pid {
+            return Ok(status);
+        }
+        let error = io::Error::last_os_error();
+        if error.kind() != io::ErrorKind::Interrupted {
+            return Err(error);
+        }
+    }
+}
+
+/// PID 1 of the sandbox. Builds the filesystem, runs the command and reaps
+/// everything until it exits, writing how long the command took to `runtime`.
+/// When init exits, the kernel kills whatever the submission left running in
+/// the namespace.
+fn init(sandbox: &Sandbox, root: &Path, command: &[String], mut runtime: Option<&fs::File>) -> io::Result<i32> {
+    check(unsafe { libc::prctl(libc::PR_SET_PDEATHSIG, libc::SIGKILL) }, "prctl")?;
+    check(
+        unsafe { libc::unshare(libc::CLONE_NEWNS | libc::CLONE_NEWNET | libc::CLONE_NEWIPC) },
+        "unshare mount, network and IPC namespaces",
+    )?;
+
+    mount(None, Path::new("/"), None, libc::MS_REC | libc::MS_PRIVATE, None)?;
+    mount(None, root, Some("tmpfs"), libc::MS_NOSUID | libc::MS_NODEV, Some("mode=0755"))?;
+
+    // Scratch space goes first, so anything exposed under /tmp lands on top of it.
+    // multiprocessing keeps its semaphores in /dev/shm
+    let dev = root.join("dev");
+    for scratch in [dev.clone(), dev.join("shm"), root.join("tmp")] {
+        fs::create_dir_all(&scratch)?;
+        mount(None, &scratch, Some("tmpfs"), libc::MS_NOSUID | libc::MS_NODEV, Some(SCRATCH_OPTIONS))?;
+    }
+    for device in DEVICES {
+        let host_device = Path::new("/dev").join(device);
+        if host_device.exists() {
+            fs::write(dev.join(device), [])?;
+            bind(&host_device, &dev.join(device), false)?;
+        }
+    }
+    for (name, target) in [
+        ("fd", "/proc/self/fd"),
+        ("stdin", "/proc/self/fd/0"),
+        ("stdout", "/proc/self/fd/1"),
+        ("stderr", "/proc/self/fd/2"),
+    ] {
+        symlink(target, dev.join(name))?;
+    }
+
+    for path in SYSTEM_PATHS.iter().map(Path::new).chain(sandbox.read_only_paths.iter().map(PathBuf::as_path)) {
+        expose(root, path, true)?;
+    }
+    expose(root, &sandbox.workdir, false)?;
+    for file in sandbox.read_only_files.iter().filter(|file| file.exists()) {
+        bind(file, &inside(root, file), true)?;
+    }
+
+    let proc_dir = root.join("proc");
+    fs::create_dir_all(&proc_dir)?;
+    mount(
+        None,
+        &proc_dir,
+        Some("proc"),
+        libc::MS_NOSUID | libc::MS_NODEV | libc::MS_NOEXEC,
+        None,
+    )?;
+
+    let old_root = root.join(".old_root");
+    fs::create_dir(&old_root)?;
+    check(
+        unsafe { libc::syscall(libc::SYS_pivot_root, c_path(root)?.as_ptr(), c_path(&old_root)?.as_ptr()) as libc::c_int },
+        "pivot_root",
+    )?;
+    std::env::set_current_dir("/")?;
+    check(unsafe { libc::umount2(c"/.old_root".as_ptr(), libc::MNT_DETACH) }, "unmount old root")?;
+    fs::remove_dir("/.old_root")?;
+    mount(
+        None,
+        Path::new("/"),
+        None,
+        libc::MS_REMOUNT | libc::MS_BIND | libc::MS_RDONLY | libc::MS_NOSUID | libc::MS_NODEV,
+        None,
+    )?;
+    std::env::set_current_dir(&sandbox.workdir)?;
+
+    let args: Vec<CString> = command
+        .iter()
+        .map(|arg| CString::new(arg.as_str()))
+        .collect::<Result<_, _>>()
+        .map_err(io::Error::other)?;
+
+    // The command runs as the same user, and mustn't get at the clock in here
+    check(unsafe { libc::prctl(libc::PR_SET_DUMPABLE, 0) }, "prctl")?;
+    let started = Instant::now();
+    let payload = check(unsafe { libc::fork() }, "fork")?;
+    if payload == 0 {
+        let mut argv: Vec<*const libc::c_char> = args.iter().map(|arg| arg.as_ptr()).collect();
+        argv.push(std::ptr::null());
+        unsafe {
+            libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0);
+            libc::execvp(argv[0], argv.as_ptr());
+        }
+        eprintln!("Failed to run {}: {}", command[0], io::Error::last_os_error());
+        unsafe { libc::_exit(127) };
+    }
+
+    // Reap orphans until the command itself exits
+    loop {
+        let mut status = 0;
+        let pid = unsafe { libc::waitpid(-1, &mut status, 0) };
+        if pid == -1 {
+            let error = io::Error::last_os_error();
+            if error.kind() == io::ErrorKind::Interrupted {
+                continue;
+            }
+            return Err(error);
+        }
+        if pid == payload {
+            if let Some(file) = runtime.as_mut() {
+                file.write_all(started.elapsed().as_nanos().to_string().as_bytes())?;
+            }
+            return Ok(if libc::WIFEXITED(status) {
+                libc::WEXITSTATUS(status)
+            } else {
+                128 + libc::WTERMSIG(status)
+            });
+        }
+    }
+}
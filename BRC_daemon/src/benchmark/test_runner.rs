@@ -1,96 +1,505 @@
+use std::collections::HashMap;
+use std::os::unix::process::CommandExt;
+use std::path::Path;
 use std::process::Stdio;
-use tokio::process::Command as TokioCommand;
-use tokio::time::{timeout, Duration, Instant};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::process::{ChildStderr, ChildStdout};
+use tokio::task::JoinHandle;
+use tokio::time::{sleep, timeout, Duration, Instant};
 use std::io;
+use serde::Serialize;
+use crate::benchmark::harness::NativeBenchmark;
+use crate::benchmark::limits::{Confinement, ResourceLimits};
+use crate::benchmark::rusage::{self, ResourceUsage, Waiter};
+use crate::benchmark::sandbox::{Sandbox, SandboxReport};
+use crate::utils::integrity::FileFingerprint;
+use crate::utils::results;
+
+pub const SUBMISSION_COMMAND: [&str; 4] = ["python", "-X", "gil=0", "main.py"];
+/// How much of the end of the logs is kept to tell why a run failed.
+const LOG_TAIL_BYTES: usize = 4096;
+/// How often the benchmark watchdog checks the runs pyperf has started.
+const WATCHDOG_INTERVAL: Duration = Duration::from_millis(100);
+/// Share of the benchmark budget a calibrated benchmark may be projected to
+/// take, leaving room for runs that come out slower than the test run.
+const CALIBRATION_BUDGET_SHARE: f64 = 0.5;
 
 pub struct TestResult {
     pub success: bool,
     pub message: String,
-    pub runtime: Option<u64>
+    /// Wall time from starting `main.py` until it was reaped.
+    pub runtime: Option<Duration>,
+    /// User and system CPU time of `main.py` and the processes it waited for.
+    pub cpu_time: Option<Duration>,
+    pub usage: Option<ResourceUsage>,
 }
 
-pub async fn run_python_test(timeout_seconds: u64) -> io::Result<TestResult> {
-    println!("Running unbenchmarked test...");
+/// Where a submission's output ends up, and so where the validator reads it.
+#[derive(Debug, Clone)]
+pub enum SubmissionOutput {
+    /// `main.py` writes this file itself.
+    File { path: String },
+    /// The daemon captures `main.py`'s stdout into this file, keeping at most
+    /// `max_bytes` of it.
+    Stdout { path: String, max_bytes: u64 },
+}
 
-    // Start the Python process
-    let mut child = TokioCommand::new("python")
-        .args(["-X", "gil=0", "main.py"])
+impl SubmissionOutput {
+    pub fn path(&self) -> &str {
+        match self {
+            SubmissionOutput::File { path } | SubmissionOutput::Stdout { path, .. } => path,
+        }
+    }
+}
+
+/// How each run of `main.py` is set up, shared by the test run and the benchmark.
+#[derive(Debug, Clone)]
+pub struct RunOptions {
+    /// Limit on a single run, in the test and in the benchmark alike.
+    pub timeout_seconds: u64,
+    /// Limit on the whole benchmark, calibration and every run included.
+    pub benchmark_budget_seconds: u64,
+    pub output: SubmissionOutput,
+    /// Cap on everything else `main.py` prints, in the test run and over the
+    /// whole benchmark.
+    pub max_log_bytes: u64,
+    /// Runs `main.py`, and the pyperf workers that time it, inside this sandbox.
+    pub sandbox: Option<Sandbox>,
+    pub limits: ResourceLimits,
+}
+
+/// How pyperf is invoked. Each of its processes runs `warmups` then
+/// `values` timed values, each of however many loops calibration settled on.
+#[derive(Debug, Clone, Serialize)]
+pub struct PyperfOptions {
+    pub processes: u32,
+    pub warmups: u32,
+    pub values: u32,
+    /// Shortest a value may take, which calibration sizes the loops to.
+    pub min_time_seconds: f64,
+    /// CPUs the worker processes are pinned to, as a list like "2-3".
+    pub affinity: Option<String>,
+}
+
+/// The settings a benchmark actually ran with, kept next to its results.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "harness", rename_all = "snake_case")]
+pub enum BenchmarkParameters {
+    Pyperf {
+        #[serde(flatten)]
+        options: PyperfOptions,
+        calibrated: bool,
+    },
+    Native { warmups: u32, runs: u32 },
+}
+
+/// Fails with the sandbox's own error if it couldn't be set up, which is the
+/// daemon's fault rather than the submission's.
+pub fn check_setup(report: Option<&SandboxReport>) -> io::Result<()> {
+    match report.and_then(SandboxReport::failure) {
+        Some(failure) => Err(io::Error::other(failure)),
+        None => Ok(()),
+    }
+}
+
+/// A process `spawn_run` started, and what it takes to wait for it and
+/// clean up after it.
+pub struct SpawnedRun {
+    pub child: std::process::Child,
+    pub process_group: u32,
+    /// Reaps the process, timing it from just before it was spawned.
+    pub waiter: Waiter,
+    pub confinement: Confinement,
+    /// Passes stderr through within the run's log budget.
+    pub log_forwarder: Option<Forwarder>,
+    /// What the sandbox reported, if the run is sandboxed.
+    pub report: Option<SandboxReport>,
+}
+
+impl SpawnedRun {
+    /// How long the command ran: as timed inside the sandbox, so setting it
+    /// up isn't counted, or else `reaped`, what the waiter measured.
+    pub fn runtime(&self, reaped: Duration) -> Duration {
+        self.report.as_ref().and_then(SandboxReport::runtime).unwrap_or(reaped)
+    }
+}
+
+/// Starts `command` in `src/` in a process group of its own, inside
+/// `sandbox` if there is one and under `limits`. Its stdout goes to
+/// `stdout`, and its stderr is passed through within `log_budget`.
+pub fn spawn_run(
+    command: &[&str],
+    sandbox: Option<&Sandbox>,
+    limits: ResourceLimits,
+    stdout: Stdio,
+    log_budget: Arc<AtomicU64>,
+) -> io::Result<SpawnedRun> {
+    let (program, report) = match sandbox {
+        Some(sandbox) => {
+            let report = SandboxReport::new(true)?;
+            (sandbox.wrap(command, &report)?, Some(report))
+        }
+        None => (command.iter().map(|arg| arg.to_string()).collect(), None),
+    };
+    let confinement = Confinement::new(limits);
+    // Spawned through std, which leaves reaping to `wait_with_usage`
+    let mut child = std::process::Command::new(&program[0]);
+    child
+        .args(&program[1..])
         .current_dir("src")
-        .stdout(Stdio::inherit())
-        .stderr(Stdio::inherit())
+        .stdout(stdout)
+        .stderr(Stdio::piped());
+    unsafe { child.pre_exec(confinement.pre_exec_hook()?) };
+    // Starting the clock before spawning counts the time to exec the interpreter
+    let start_time = Instant::now();
+    let mut child = child
         .spawn()
-        .map_err(|e| io::Error::new(io::ErrorKind::Other, 
-            format!("Failed to run main.py: {}", e)))?;
+        .map_err(|e| io::Error::other(format!("Failed to start {}: {}", command.join(" "), e)))?;
+    let process_group = child.id();
+    let waiter = rusage::wait_with_usage(process_group, start_time);
+    let log_forwarder = child
+        .stderr
+        .take()
+        .map(ChildStderr::from_std)
+        .transpose()?
+        .map(|stderr| tokio::spawn(forward_bounded(stderr, tokio::io::stderr(), log_budget)));
 
-    let start_time = Instant::now();
+    Ok(SpawnedRun { child, process_group, waiter, confinement, log_forwarder, report })
+}
 
+/// What a benchmark that ran to the end left behind.
+#[derive(Debug)]
+pub enum BenchmarkResult {
+    /// pyperf wrote its results to the benchmark file, having used this much.
+    Pyperf(ResourceUsage),
+    /// The native harness kept its timings itself.
+    Native(NativeBenchmark),
+}
+
+/// How the benchmark ended.
+#[derive(Debug)]
+pub enum BenchmarkRun {
+    Finished(BenchmarkResult),
+    /// The watchdog stopped it. `values` are the timings in seconds
+    /// collected by then.
+    Stopped { reason: String, values: Vec<f64> },
+}
+
+/// Whether `pid` is a run of the submission itself rather than pyperf, the
+/// sandbox or an interpreter shim on the way to it.
+fn is_submission_run(pid: libc::pid_t) -> bool {
+    let Ok(cmdline) = std::fs::read(format!("/proc/{}/cmdline", pid)) else {
+        return false;
+    };
+    let args: Vec<&[u8]> = cmdline.strip_suffix(&[0]).unwrap_or(&cmdline).split(|&byte| byte == 0).collect();
+    args.len() == SUBMISSION_COMMAND.len()
+        && args[1..].iter().zip(&SUBMISSION_COMMAND[1..]).all(|(arg, expected)| *arg == expected.as_bytes())
+}
+
+/// Reads a timing from a line pyperf prints in verbose mode, such as
+/// "Value 3: 70.7 ms (loops: 1, raw: 70.7 ms)".
+fn reported_value(line: &str) -> Option<f64> {
+    let (_, measurement) = line.trim().strip_prefix("Value ")?.split_once(": ")?;
+    let mut parts = measurement.split_whitespace();
+    let value: f64 = parts.next()?.parse().ok()?;
+    let scale = match parts.next()? {
+        "ns" => 1e-9,
+        "us" => 1e-6,
+        "ms" => 1e-3,
+        "sec" => 1.0,
+        _ => return None,
+    };
+    Some(value * scale)
+}
+
+/// What a forwarder saw: whether anything was dropped, and the last bytes read.
+pub struct Forwarded {
+    pub exceeded: bool,
+    pub tail: Vec<u8>,
+}
+
+pub type Forwarder = JoinHandle<io::Result<Forwarded>>;
+
+/// Copies `reader` into `writer` while `remaining` allows, then keeps draining
+/// it so the child never blocks on a full pipe. `remaining` can be shared by
+/// several streams.
+pub async fn forward_bounded(
+    mut reader: impl AsyncRead + Unpin,
+    mut writer: impl AsyncWrite + Unpin,
+    remaining: Arc<AtomicU64>,
+) -> io::Result<Forwarded> {
+    let mut buffer = vec![0u8; 64 * 1024];
+    let mut exceeded = false;
+    let mut tail = Vec::new();
+
+    loop {
+        let read = reader.read(&mut buffer).await?;
+        if read == 0 {
+            break;
+        }
+        tail.extend_from_slice(&buffer[..read]);
+        tail.drain(..tail.len().saturating_sub(LOG_TAIL_BYTES));
+        let left = remaining
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |left| Some(left.saturating_sub(read as u64)))
+            .unwrap_or_else(|left| left);
+        let allowed = left.min(read as u64) as usize;
+        if allowed < read {
+            exceeded = true;
+        }
+        if allowed > 0 {
+            writer.write_all(&buffer[..allowed]).await?;
+        }
+    }
+
+    writer.flush().await?;
+    Ok(Forwarded { exceeded, tail })
+}
+
+pub async fn join_forwarder(forwarder: &mut Forwarder) -> io::Result<Forwarded> {
+    forwarder
+        .await
+        .map_err(|e| io::Error::other(format!("Failed to capture process output: {}", e)))?
+        .map_err(|e| io::Error::other(format!("Failed to capture process output: {}", e)))
+}
+
+/// Runs `main.py` once. Its output goes to `options.output`, and whatever else
+/// it prints is passed through up to `options.max_log_bytes` in total.
+pub async fn run_python_test(options: &RunOptions) -> io::Result<TestResult> {
+    let RunOptions { timeout_seconds, ref output, max_log_bytes, ref sandbox, limits, .. } = *options;
+    println!("Running unbenchmarked test...");
+
+    // A stale output from an earlier run must never be validated
+    std::fs::remove_file(output.path()).unwrap_or_default();
+
+    // Start the Python process
+    let log_budget = Arc::new(AtomicU64::new(max_log_bytes));
+    let mut run = spawn_run(&SUBMISSION_COMMAND, sandbox.as_ref(), limits, Stdio::piped(), log_budget.clone())?;
+    let process_group = run.process_group;
+
+    let mut output_capture: Option<Forwarder> = None;
+    let mut log_forwarders: Vec<Forwarder> = run.log_forwarder.take().into_iter().collect();
+    if let Some(child_stdout) = run.child.stdout.take().map(ChildStdout::from_std).transpose()? {
+        match output {
+            SubmissionOutput::File { .. } => log_forwarders.push(tokio::spawn(forward_bounded(
+                child_stdout,
+                tokio::io::stdout(),
+                log_budget,
+            ))),
+            SubmissionOutput::Stdout { path, max_bytes } => {
+                let file = tokio::fs::File::create(path).await?;
+                output_capture = Some(tokio::spawn(forward_bounded(
+                    child_stdout,
+                    file,
+                    Arc::new(AtomicU64::new(*max_bytes)),
+                )));
+            }
+        }
+    }
     // Timeout wrapper
-    match timeout(Duration::from_secs(timeout_seconds), async {
-        let status = child.wait().await
-            .map_err(|e| io::Error::new(io::ErrorKind::Other, 
-                format!("Failed to wait for process: {}", e)))?;
+    let finished = timeout(Duration::from_secs(timeout_seconds), async {
+        let (status, reaped, usage) = rusage::join_waiter(&mut run.waiter).await?;
+        let runtime = run.runtime(reaped);
         println!("Process exited with: {}", status);
-        Ok::<_, io::Error>(status)
-    }).await {
+        println!("Resource usage: {}", usage.summary());
+
+        // Anything main.py left behind would hold the pipes open and keep
+        // burning CPU into the next run
+        let stopped = run.confinement.terminate(process_group).await?;
+        if stopped > 0 {
+            println!("Stopped {} processes main.py left running", stopped);
+        }
+
+        let output_exceeded = match output_capture.as_mut() {
+            Some(forwarder) => join_forwarder(forwarder).await?.exceeded,
+            None => false,
+        };
+        let mut logs_exceeded = false;
+        let mut log_tail = Vec::new();
+        for forwarder in log_forwarders.iter_mut() {
+            let forwarded = join_forwarder(forwarder).await?;
+            logs_exceeded |= forwarded.exceeded;
+            log_tail.extend(forwarded.tail);
+        }
+        let limit_exceeded = run.confinement.exceeded(status, &log_tail);
+        Ok::<_, io::Error>((status, runtime, usage, output_exceeded, logs_exceeded, limit_exceeded))
+    }).await;
+    if finished.is_ok() {
+        check_setup(run.report.as_ref())?;
+    }
+
+    match finished {
         Ok(status_result) => {
             match status_result {
-                Ok(status) => {
-                    let elapsed_ms: u64 = start_time.elapsed().as_secs();
+                Ok((status, runtime, usage, output_exceeded, logs_exceeded, limit_exceeded)) => {
+                    if let SubmissionOutput::Stdout { max_bytes, .. } = output
+                        && output_exceeded
+                    {
+                        return Ok(TestResult {
+                            success: false,
+                            message: format!("Output on stdout exceeded the limit of {} bytes", max_bytes),
+                            runtime: Some(runtime),
+                            cpu_time: Some(usage.cpu_time()),
+                            usage: Some(usage),
+                        });
+                    }
+                    if logs_exceeded {
+                        return Ok(TestResult {
+                            success: false,
+                            message: format!("Log output exceeded the limit of {} bytes", max_log_bytes),
+                            runtime: Some(runtime),
+                            cpu_time: Some(usage.cpu_time()),
+                            usage: Some(usage),
+                        });
+                    }
+                    if let Some(limit) = limit_exceeded {
+                        return Ok(TestResult {
+                            success: false,
+                            message: limit.to_string(),
+                            runtime: Some(runtime),
+                            cpu_time: Some(usage.cpu_time()),
+                            usage: Some(usage),
+                        });
+                    }
                     // Process finished before timeout
                     if !status.success() {
                         return Ok(TestResult {
                             success: false,
                             message: format!("Python process failed with non-zero exit code: {}", status),
-                            runtime: Some(elapsed_ms)
+                            runtime: Some(runtime),
+                            cpu_time: Some(usage.cpu_time()),
+                            usage: Some(usage),
                         });
                     }
-                    println!("Process completed successfully in {}s", elapsed_ms);
+                    println!("Process completed successfully in {:.3?}", runtime);
                     Ok(TestResult {
                         success: true,
                         message: "Test completed successfully".to_string(),
-                        runtime: Some(elapsed_ms)
+                        runtime: Some(runtime),
+                        cpu_time: Some(usage.cpu_time()),
+                        usage: Some(usage),
                     })
                 },
                 Err(e) => Ok(TestResult {
                     success: false,
                     message: e.to_string(),
-                    runtime: None
+                    runtime: None,
+                    cpu_time: None,
+                    usage: None,
                 }),
             }
         },
         Err(_) => {
             // Timeout triggered
             println!("Timer won the race! Process took too long, killing it.");
+            for forwarder in output_capture.iter().chain(&log_forwarders) {
+                forwarder.abort();
+            }
 
-            if let Err(e) = child.kill().await {
-                eprintln!("Failed to kill process: {}", e);
-            } else {
-                let _ = child.wait().await;
+            let mut message = format!("Process timed out after {} seconds", timeout_seconds);
+            if let Err(e) = run.confinement.terminate(process_group).await {
+                eprintln!("Failed to stop process: {}", e);
+                message = format!("{}. {}", message, e);
             }
-            
+            let usage = rusage::join_waiter(&mut run.waiter).await.ok().map(|(_, _, usage)| usage);
+
             Ok(TestResult {
                 success: false,
-                message: format!("Process timed out after {} seconds", timeout_seconds),
-                runtime: None
+                message,
+                runtime: None,
+                cpu_time: usage.map(|usage| usage.cpu_time()),
+                usage,
             })
         }
     }
 }
 
-pub fn run_benchmark(benchmark_file_name: &str, skip_calibration: bool) -> io::Result<std::process::ExitStatus> {
+/// Projects how long pyperf will take from the test run's `runtime`, with or
+/// without calibration. Calibration doubles the loop count until a value
+/// takes at least the minimum time, then every warmup and value runs that
+/// many loops.
+pub fn projected_benchmark_time(runtime: Duration, calibrate: bool, pyperf: &PyperfOptions) -> Duration {
+    // Counted in f64, since a long minimum time over a fast run takes more
+    // loops than an integer holds
+    let runs_per_value = if calibrate && !runtime.is_zero() {
+        let loops = (pyperf.min_time_seconds / runtime.as_secs_f64()).ceil().max(1.0);
+        loops.log2().ceil().exp2()
+    } else {
+        1.0
+    };
+    // Reaching 2^k loops takes 1 + 2 + ... + 2^k runs
+    let calibration_runs = if calibrate { 2.0 * runs_per_value - 1.0 } else { 0.0 };
+    let runs = calibration_runs
+        + f64::from(pyperf.processes) * (f64::from(pyperf.warmups) + f64::from(pyperf.values)) * runs_per_value;
+    Duration::try_from_secs_f64(runtime.as_secs_f64() * runs).unwrap_or(Duration::MAX)
+}
+
+/// Whether the benchmark should calibrate its loop count, which is worth it
+/// as long as the calibrated benchmark is projected to fit the budget.
+pub fn should_calibrate(runtime: Option<Duration>, benchmark_budget_seconds: u64, pyperf: &PyperfOptions) -> bool {
+    let Some(runtime) = runtime else {
+        return false;
+    };
+    let budget = Duration::from_secs(benchmark_budget_seconds).mul_f64(CALIBRATION_BUDGET_SHARE);
+    let projected = projected_benchmark_time(runtime, true, pyperf);
+    println!(
+        "Projected benchmark time: {:.3?} with calibration, {:.3?} without",
+        projected,
+        projected_benchmark_time(runtime, false, pyperf)
+    );
+    projected <= budget
+}
+
+/// Runs pyperf over `main.py`, writing its results to `benchmark_path`, which
+/// must be absolute since pyperf runs inside `src/`. With a sandbox, only
+/// pyperf's workers run inside it, each timing `main.py` from in there, so
+/// setting it up isn't measured and the results stay out of the submission's
+/// reach.
+///
+/// A watchdog stops the whole benchmark when a single run of `main.py` takes
+/// longer than `timeout_seconds`, everything together goes past
+/// `benchmark_budget_seconds` or the testcase at `testcase_path` is touched.
+/// Everything printed on stderr, pyperf's own
+/// messages included, shares a single `max_log_bytes` budget.
+pub async fn run_benchmark(
+    benchmark_path: &Path,
+    skip_calibration: bool,
+    pyperf: &PyperfOptions,
+    options: &RunOptions,
+    testcase_path: &str,
+    testcase: &FileFingerprint,
+) -> io::Result<BenchmarkRun> {
     println!("Running benchmark...");
 
     // Clean up old benchmark file if it exists
-    std::fs::remove_file(benchmark_file_name).unwrap_or_default();
+    std::fs::remove_file(benchmark_path).unwrap_or_default();
 
-    let output_path: String = format!("../{}", benchmark_file_name);
+    let output_path = benchmark_path.to_string_lossy();
+    let processes = pyperf.processes.to_string();
+    let warmups = pyperf.warmups.to_string();
+    let values = pyperf.values.to_string();
+    let min_time = pyperf.min_time_seconds.to_string();
+    // Verbose output reports each value as it's measured, which is all there
+    // is to show if the benchmark has to be stopped
+    // -P keeps a pyperf directory in the submission from shadowing the real one
     let mut args: Vec<&str> = vec![
+        "python",
+        "-P",
         "-X", "gil=0",
         "-m", "pyperf",
         "command",
         "-o", &output_path,
-        "-p", "1",
+        "-p", &processes,
+        "-w", &warmups,
+        "-n", &values,
+        "--min-time", &min_time,
+        "-v",
     ];
+    if let Some(affinity) = &pyperf.affinity {
+        args.push("--affinity");
+        args.push(affinity);
+    }
 
     println!("Skipping calibration in benchmark: {}", skip_calibration);
 
@@ -98,22 +507,178 @@ pub fn run_benchmark(benchmark_file_name: &str, skip_calibration: bool) -> io::R
         args.push("--loops");
         args.push("1");
     }
-    
-    args.extend(["--", "python", "-X", "gil=0", "main.py"]);
 
-    let mut child = std::process::Command::new("python")
-        .args(args)
-        .current_dir("src") 
-        .stdout(Stdio::inherit())
-        .stderr(Stdio::inherit())
-        .spawn()
-        .map_err(|e| io::Error::new(io::ErrorKind::Other, 
-            format!("Failed to run benchmark: {}", e)))?;
+    let launcher = results::private_path(&format!("pyperf-worker-{}", uuid::Uuid::new_v4()));
+    let launcher_path = launcher.to_string_lossy();
+    // The workers time main.py themselves, so theirs isn't
+    let report = match &options.sandbox {
+        Some(sandbox) => {
+            let report = SandboxReport::new(false)?;
+            sandbox.launcher(&launcher, &["python", "-P", "-X", "gil=0"], &report)?;
+            args.push("--python");
+            args.push(&launcher_path);
+            Some(report)
+        }
+        None => None,
+    };
+
+    args.push("--");
+    args.extend(SUBMISSION_COMMAND);
+
+    // pyperf shares the limits with the runs it starts
+    let log_budget = Arc::new(AtomicU64::new(options.max_log_bytes));
+    let mut run = spawn_run(&args, None, options.limits, Stdio::piped(), log_budget)?;
+    let process_group = run.process_group;
+    let reader = run.child.stdout.take().map(ChildStdout::from_std).transpose()?.map(|stdout| {
+        tokio::spawn(async move {
+            let mut values = Vec::new();
+            let mut lines = BufReader::new(stdout).lines();
+            while let Ok(Some(line)) = lines.next_line().await {
+                println!("{}", line);
+                values.extend(reported_value(&line));
+            }
+            values
+        })
+    });
 
-    let status = child.wait()
-        .map_err(|e| io::Error::new(io::ErrorKind::Other, 
-            format!("Failed to wait for benchmark process: {}", e)))?;
+    let run_timeout = Duration::from_secs(options.timeout_seconds);
+    let deadline = Instant::now() + Duration::from_secs(options.benchmark_budget_seconds);
+    let mut first_seen: HashMap<libc::pid_t, Instant> = HashMap::new();
+    // The loop ends with the exit status, or why the watchdog had to stop pyperf
+    let outcome = loop {
+        tokio::select! {
+            exited = &mut run.waiter => {
+                let (status, _, usage) = exited
+                    .map_err(|e| io::Error::other(format!("Failed to wait for benchmark process: {}", e)))??;
+                println!("Benchmark process exited: {}", status);
+                println!("Benchmark resource usage: {}", usage.summary());
+                break Ok((status, usage));
+            }
+            _ = sleep(WATCHDOG_INTERVAL) => {
+                let now = Instant::now();
+                if now >= deadline {
+                    break Err(format!(
+                        "Benchmark exceeded its budget of {} seconds",
+                        options.benchmark_budget_seconds
+                    ));
+                }
+                if let Some(change) = testcase.touched(testcase_path) {
+                    break Err(format!("Input modified during the benchmark: {}", change));
+                }
+
+                let running: Vec<libc::pid_t> = run.confinement
+                    .processes(process_group as libc::pid_t)
+                    .into_iter()
+                    .filter(|&pid| is_submission_run(pid))
+                    .collect();
+                first_seen.retain(|pid, _| running.contains(pid));
+                for pid in running {
+                    first_seen.entry(pid).or_insert(now);
+                }
+                if first_seen.values().any(|&started| now - started > run_timeout) {
+                    break Err(format!(
+                        "A benchmark run timed out after {} seconds",
+                        options.timeout_seconds
+                    ));
+                }
+            }
+        }
+    };
+
+    if let Err(reason) = &outcome {
+        println!("{}, stopping the benchmark", reason);
+    }
+    let stopped = run.confinement.terminate(process_group).await?;
+    std::fs::remove_file(&launcher).unwrap_or_default();
+    if outcome.is_err() {
+        let _ = rusage::join_waiter(&mut run.waiter).await;
+    } else if stopped > 0 {
+        println!("Stopped {} processes left running by the benchmark", stopped);
+    }
+    let values = match reader {
+        Some(reader) => reader.await.unwrap_or_default(),
+        None => Vec::new(),
+    };
+    let (logs_exceeded, log_tail) = match run.log_forwarder.as_mut() {
+        Some(forwarder) => {
+            let forwarded = join_forwarder(forwarder).await?;
+            (forwarded.exceeded, forwarded.tail)
+        }
+        None => (false, Vec::new()),
+    };
+
+    match outcome {
+        Ok((status, usage)) => {
+            check_setup(report.as_ref())?;
+            if logs_exceeded {
+                return Err(io::Error::other(format!(
+                    "Log output exceeded the limit of {} bytes during the benchmark",
+                    options.max_log_bytes
+                )));
+            }
+            if let Some(limit) = run.confinement.exceeded(status, &log_tail) {
+                return Err(io::Error::other(limit.to_string()));
+            }
+            Ok(BenchmarkRun::Finished(BenchmarkResult::Pyperf(usage)))
+        }
+        Err(reason) => Ok(BenchmarkRun::Stopped { reason, values }),
+    }
+}
 
-    println!("Benchmark process exited: {}", status);
-    Ok(status)
-}
\ No newline at end of file
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pyperf(min_time_seconds: f64) -> PyperfOptions {
+        PyperfOptions {
+            processes: 2,
+            warmups: 1,
+            values: 3,
+            min_time_seconds,
+            affinity: None,
+        }
+    }
+
+    #[test]
+    fn reported_values_are_read_in_seconds() {
+        for (line, expected) in [
+            ("Value 3: 70.7 ms (loops: 1, raw: 70.7 ms)", 70.7e-3),
+            ("  Value 1: 1.25 sec (loops: 2, raw: 2.50 sec)", 1.25),
+            ("Value 12: 850 us (loops: 16, raw: 13.6 ms)", 850e-6),
+            ("Value 2: 12 ns", 12e-9),
+        ] {
+            let value = reported_value(line).unwrap();
+            assert!((value - expected).abs() < expected * 1e-9, "{}: {}", line, value);
+        }
+    }
+
+    #[test]
+    fn other_lines_report_no_value() {
+        for line in [
+            "Warmup 1: 71.2 ms (loops: 1, raw: 71.2 ms)",
+            "Calibration 1: 70.1 ms (loops: 1)",
+            "Value 1: 70.7 min",
+            "Value 1: fast ms",
+            "Mean +- std dev: 70.7 ms +- 1.2 ms",
+            "",
+        ] {
+            assert_eq!(reported_value(line), None, "{}", line);
+        }
+    }
+
+    #[test]
+    fn calibration_rounds_loops_up_to_a_power_of_two() {
+        let runtime = Duration::from_millis(30);
+        // 0.1 / 0.03 rounds up to 4 loops, reached in 1 + 2 + 4 runs
+        assert_eq!(projected_benchmark_time(runtime, true, &pyperf(0.1)), runtime * (7 + 2 * 4 * 4));
+        assert_eq!(projected_benchmark_time(runtime, false, &pyperf(0.1)), runtime * 2 * 4);
+    }
+
+    #[test]
+    fn projection_saturates_instead_of_overflowing() {
+        let projected = projected_benchmark_time(Duration::from_nanos(1), true, &pyperf(1e12));
+        assert!(projected > Duration::from_secs(1_000_000_000));
+        assert!(!should_calibrate(Some(Duration::from_nanos(1)), 600, &pyperf(1e12)));
+        assert_eq!(projected_benchmark_time(Duration::from_secs(1), true, &pyperf(f64::MAX)), Duration::MAX);
+    }
+}
@@ -0,0 +1,32 @@
+use std::fs;
+use std::io;
+use crate::testcase::commitment::Commitment;
+use crate::utils::config::LevelConfig;
+use crate::utils::file_manager;
+
+/// `daemon commit`: prepares the level's testcase for distribution. The answer
+/// is replaced by a salted commitment, so the testcase and commitment files
+/// can be shipped to contestants without the answer in readable form. See
+/// `Commitment` for how little that hides from someone set on recovering it.
+pub async fn run(level_config: &LevelConfig, num_rows: usize) -> io::Result<()> {
+    file_manager::ensure_output_dir()?;
+    let testcase_id = file_manager::find_or_create_testcase(num_rows, level_config.collation).await?;
+
+    let answer_path = file_manager::answer_path(num_rows, &testcase_id);
+    let answer_lines = file_manager::read_lines_from_file(&answer_path)?;
+    let commitment = Commitment::new(&answer_lines)?;
+
+    let commitment_path = file_manager::commitment_path(num_rows, &testcase_id);
+    commitment.write(&commitment_path)?;
+    fs::remove_file(&answer_path)?;
+
+    println!(
+        "Committed to {} stations. Distribute {}/testcase_{}_{}.txt with {}",
+        commitment.stations.len(),
+        file_manager::TESTCASE_PATH,
+        num_rows,
+        testcase_id,
+        commitment_path
+    );
+    Ok(())
+}
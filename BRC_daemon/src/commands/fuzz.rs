@@ -0,0 +1,88 @@
+use std::fs;
+use std::io;
+use std::time::{SystemTime, UNIX_EPOCH};
+use crate::benchmark::test_runner;
+use crate::testcase::{generator, solver};
+use crate::testcase::validator;
+use crate::utils::config::LevelConfig;
+use super::{join_lines, write_testcase, TestcaseBackup, TESTCASE_PATH};
+
+const DEFAULT_RUNS: u64 = 100;
+const FAILING_TESTCASE_PATH: &str = "output/fuzz_testcase.txt";
+
+/// `daemon fuzz [runs] [seed]`: runs `main.py` against small seeded testcases
+/// and stops on the first one whose output differs from the reference.
+pub async fn run(level_config: &LevelConfig, timeout_seconds: u64, args: &[String]) -> io::Result<()> {
+    let parse_arg = |index: usize, name: &str| -> io::Result<Option<u64>> {
+        args.get(index)
+            .map(|arg| {
+                arg.parse::<u64>().map_err(|e| {
+                    io::Error::new(io::ErrorKind::InvalidInput, format!("Invalid {} '{}': {}", name, arg, e))
+                })
+            })
+            .transpose()
+    };
+    let runs = parse_arg(0, "run count")?.unwrap_or(DEFAULT_RUNS);
+    let first_seed = match parse_arg(1, "seed")? {
+        Some(seed) => seed,
+        None => SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_nanos() as u64)
+            .unwrap_or_default(),
+    };
+
+    println!("Fuzzing {} testcases starting at seed {}", runs, first_seed);
+
+    let backup = TestcaseBackup::take();
+    let result = fuzz(level_config, timeout_seconds, first_seed, runs).await;
+    backup.restore(level_config.submission_output().path())?;
+
+    match result? {
+        None => {
+            println!("No divergence in {} testcases", runs);
+            Ok(())
+        }
+        Some((seed, message)) => {
+            println!("Divergence at seed {}: {}", seed, message);
+            println!("Failing input written to {}", FAILING_TESTCASE_PATH);
+            println!("Replay it with: daemon fuzz 1 {}", seed);
+            println!("Shrink it with: daemon minimize {}", FAILING_TESTCASE_PATH);
+            Err(io::Error::other(format!("Output diverged from the reference at seed {}", seed)))
+        }
+    }
+}
+
+async fn fuzz(
+    level_config: &LevelConfig,
+    timeout_seconds: u64,
+    first_seed: u64,
+    runs: u64,
+) -> io::Result<Option<(u64, String)>> {
+    let options = level_config.validation_options();
+    let run_options = level_config.run_options(timeout_seconds)?;
+    let output = &run_options.output;
+
+    for seed in (0..runs).map(|i| first_seed.wrapping_add(i)) {
+        let (profile, lines) = generator::generate_small_testcase(seed);
+        println!("Seed {} ({:?}, {} rows)", seed, profile, lines.len());
+
+        write_testcase(lines.iter().map(String::as_str))?;
+        let expected = solver::solve(lines.iter().cloned().map(Ok), options.collation)?;
+
+        let test_result = test_runner::run_python_test(&run_options).await?;
+        let failure = if !test_result.success {
+            Some(test_result.message)
+        } else {
+            let result = validator::validate_output(&expected, output.path(), TESTCASE_PATH, options)?;
+            (!result.success).then_some(result.message)
+        };
+
+        if let Some(message) = failure {
+            fs::create_dir_all("output")?;
+            fs::write(FAILING_TESTCASE_PATH, join_lines(lines.iter().map(String::as_str)))?;
+            return Ok(Some((seed, message)));
+        }
+    }
+
+    Ok(None)
+}
@@ -0,0 +1,233 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io;
+use std::mem::{self, Discriminant};
+use crate::benchmark::test_runner::{self, RunOptions};
+use crate::testcase::solver;
+use crate::testcase::validator::{self, Difference, ParseErrorKind, ValidationOptions, ValidationResult};
+use crate::utils::config::LevelConfig;
+use super::{join_lines, write_testcase, TestcaseBackup, TESTCASE_PATH};
+
+const MAX_RUNS: usize = 500;
+
+const MINIMIZED_INPUT_PATH: &str = "output/minimized_testcase.txt";
+const MINIMIZED_EXPECTED_PATH: &str = "output/minimized_expected.txt";
+const MINIMIZED_ACTUAL_PATH: &str = "output/minimized_actual.txt";
+
+struct Reproduction {
+    input: Vec<usize>,
+    expected: Vec<String>,
+    actual: Vec<u8>,
+    message: String,
+}
+
+/// One part of a failure: a kind of difference at a station, a kind of
+/// malformed line, or a failure the validator only has a message for.
+#[derive(Debug, PartialEq, Eq, Hash)]
+enum Symptom {
+    Difference(Discriminant<Difference>, String),
+    Malformed(Discriminant<ParseErrorKind>),
+    Failure(String),
+}
+
+fn symptoms(result: &ValidationResult) -> HashSet<Symptom> {
+    let Some(report) = &result.report else {
+        return HashSet::from([Symptom::Failure(result.message.clone())]);
+    };
+    let differences = report
+        .differences
+        .iter()
+        .map(|difference| Symptom::Difference(mem::discriminant(difference), difference.station().to_string()));
+    let malformed = report
+        .parse_errors
+        .iter()
+        .map(|error| Symptom::Malformed(mem::discriminant(&error.kind)));
+    differences.chain(malformed).collect()
+}
+
+/// Shrinks a failing testcase with delta debugging, first dropping whole
+/// stations and then single lines, until every remaining line is needed to
+/// reproduce the mismatch. A smaller input only counts when its failure is
+/// made of symptoms the original one had, so shrinking can't drift to a
+/// different bug, such as a wrong value turning into a missing station.
+struct Minimizer<'a> {
+    lines: Vec<&'a str>,
+    /// The symptoms of the failure on the whole testcase.
+    signature: Option<HashSet<Symptom>>,
+    options: ValidationOptions,
+    run_options: RunOptions,
+    runs: usize,
+    smallest: Option<Reproduction>,
+}
+
+impl<'a> Minimizer<'a> {
+    async fn reproduces(&mut self, input: Vec<usize>) -> io::Result<bool> {
+        self.runs += 1;
+
+        write_testcase(input.iter().map(|&i| self.lines[i]))?;
+
+        let expected = solver::solve(input.iter().map(|&i| Ok(self.lines[i].to_string())), self.options.collation)?;
+
+        // A crash or timeout is a different bug, not a smaller copy of this one
+        let test_result = test_runner::run_python_test(&self.run_options).await?;
+        if !test_result.success {
+            return Ok(false);
+        }
+
+        let result = validator::validate_output(&expected, self.run_options.output.path(), TESTCASE_PATH, self.options)?;
+        if result.success {
+            return Ok(false);
+        }
+        let symptoms = symptoms(&result);
+        match &self.signature {
+            Some(signature) if symptoms.is_empty() || !symptoms.is_subset(signature) => {
+                println!("Failing differently with {} lines: {}", input.len(), result.message);
+                return Ok(false);
+            }
+            Some(_) => {}
+            None => self.signature = Some(symptoms),
+        }
+
+        println!("Still failing with {} lines: {}", input.len(), result.message);
+        self.smallest = Some(Reproduction {
+            input,
+            expected,
+            actual: fs::read(self.run_options.output.path()).unwrap_or_default(),
+            message: result.message,
+        });
+        Ok(true)
+    }
+
+    async fn ddmin(&mut self, mut groups: Vec<Vec<usize>>) -> io::Result<Vec<Vec<usize>>> {
+        let mut granularity = 2;
+
+        while groups.len() >= 2 && self.runs < MAX_RUNS {
+            let chunk_size = groups.len().div_ceil(granularity);
+            let chunks: Vec<Vec<Vec<usize>>> = groups.chunks(chunk_size).map(<[_]>::to_vec).collect();
+            let mut reduced = false;
+
+            for chunk in &chunks {
+                if self.reproduces(flatten(chunk)).await? {
+                    groups = chunk.clone();
+                    granularity = 2;
+                    reduced = true;
+                    break;
+                }
+            }
+
+            // With two chunks each complement is just the other chunk
+            if !reduced && chunks.len() > 2 {
+                for skipped in 0..chunks.len() {
+                    let complement: Vec<Vec<usize>> = chunks
+                        .iter()
+                        .enumerate()
+                        .filter(|(i, _)| *i != skipped)
+                        .flat_map(|(_, chunk)| chunk.iter().cloned())
+                        .collect();
+                    if self.reproduces(flatten(&complement)).await? {
+                        groups = complement;
+                        granularity = (granularity - 1).max(2);
+                        reduced = true;
+                        break;
+                    }
+                }
+            }
+
+            if !reduced {
+                if granularity >= groups.len() {
+                    break;
+                }
+                granularity = (granularity * 2).min(groups.len());
+            }
+        }
+
+        Ok(groups)
+    }
+}
+
+fn flatten(groups: &[Vec<usize>]) -> Vec<usize> {
+    let mut input: Vec<usize> = groups.iter().flatten().copied().collect();
+    input.sort_unstable();
+    input
+}
+
+fn group_by_station(lines: &[&str], input: &[usize]) -> Vec<Vec<usize>> {
+    let mut station_groups: Vec<Vec<usize>> = Vec::new();
+    let mut station_index: HashMap<&str, usize> = HashMap::new();
+
+    for &i in input {
+        let station = lines[i].split_once(';').map(|(station, _)| station).unwrap_or(lines[i]);
+        let group = *station_index.entry(station).or_insert_with(|| {
+            station_groups.push(Vec::new());
+            station_groups.len() - 1
+        });
+        station_groups[group].push(i);
+    }
+
+    station_groups
+}
+
+/// `daemon minimize [testcase]`: shrinks the testcase the submission fails on
+/// and writes the smallest failing input with both outputs to `output/`.
+pub async fn run(level_config: &LevelConfig, timeout_seconds: u64, testcase_path: Option<&str>) -> io::Result<()> {
+    let testcase_path = testcase_path.unwrap_or(TESTCASE_PATH);
+    let original = fs::read_to_string(testcase_path)?;
+    let run_options = level_config.run_options(timeout_seconds)?;
+    let backup = TestcaseBackup::take();
+    let lines: Vec<&str> = original.lines().filter(|line| !line.trim().is_empty()).collect();
+
+    println!("Minimizing testcase {} ({} lines)", testcase_path, lines.len());
+
+    let mut minimizer = Minimizer {
+        lines,
+        signature: None,
+        options: level_config.validation_options(),
+        run_options,
+        runs: 0,
+        smallest: None,
+    };
+
+    let result = minimize(&mut minimizer).await;
+
+    // Put the original testcase back whatever happened
+    backup.restore(minimizer.run_options.output.path())?;
+    result?;
+
+    let Some(smallest) = minimizer.smallest else {
+        return Err(io::Error::other("The testcase does not reproduce a mismatch, nothing to minimize"));
+    };
+
+    fs::create_dir_all("output")?;
+    fs::write(MINIMIZED_INPUT_PATH, join_lines(smallest.input.iter().map(|&i| minimizer.lines[i])))?;
+    fs::write(MINIMIZED_EXPECTED_PATH, join_lines(smallest.expected.iter().map(String::as_str)))?;
+    fs::write(MINIMIZED_ACTUAL_PATH, &smallest.actual)?;
+
+    println!(
+        "Minimized to {} lines after {} runs: {}",
+        smallest.input.len(),
+        minimizer.runs,
+        smallest.message
+    );
+    println!("Input: {}", MINIMIZED_INPUT_PATH);
+    println!("Expected output: {}", MINIMIZED_EXPECTED_PATH);
+    println!("Your output: {}", MINIMIZED_ACTUAL_PATH);
+    Ok(())
+}
+
+async fn minimize(minimizer: &mut Minimizer<'_>) -> io::Result<()> {
+    let everything: Vec<usize> = (0..minimizer.lines.len()).collect();
+    if !minimizer.reproduces(everything.clone()).await? {
+        return Ok(());
+    }
+
+    let stations = group_by_station(&minimizer.lines, &everything);
+    let remaining = flatten(&minimizer.ddmin(stations).await?);
+
+    let single_lines = remaining.into_iter().map(|i| vec![i]).collect();
+    minimizer.ddmin(single_lines).await?;
+
+    if minimizer.runs >= MAX_RUNS {
+        println!("Stopped after {} runs, the result may not be minimal", MAX_RUNS);
+    }
+    Ok(())
+}
@@ -0,0 +1,47 @@
+pub mod commit;
+pub mod fuzz;
+pub mod minimize;
+
+use std::fs;
+use std::io;
+
+const TESTCASE_PATH: &str = "src/testcase.txt";
+
+/// A copy of `src/testcase.txt` taken before a command starts rewriting it.
+struct TestcaseBackup(Option<Vec<u8>>);
+
+impl TestcaseBackup {
+    fn take() -> Self {
+        TestcaseBackup(fs::read(TESTCASE_PATH).ok())
+    }
+
+    fn restore(self, output_path: &str) -> io::Result<()> {
+        fs::remove_file(output_path).unwrap_or_default();
+        match self.0 {
+            Some(contents) => {
+                fs::remove_file(TESTCASE_PATH).unwrap_or_default();
+                fs::write(TESTCASE_PATH, contents)
+            }
+            None => {
+                fs::remove_file(TESTCASE_PATH).unwrap_or_default();
+                Ok(())
+            }
+        }
+    }
+}
+
+fn join_lines<'a>(lines: impl Iterator<Item = &'a str>) -> String {
+    let mut contents = String::new();
+    for line in lines {
+        contents.push_str(line);
+        contents.push('\n');
+    }
+    contents
+}
+
+/// Writes a testcase where `main.py` reads it. The old one is removed first
+/// since a normal run leaves it read-only.
+fn write_testcase<'a>(lines: impl Iterator<Item = &'a str>) -> io::Result<()> {
+    fs::remove_file(TESTCASE_PATH).unwrap_or_default();
+    fs::write(TESTCASE_PATH, join_lines(lines))
+}
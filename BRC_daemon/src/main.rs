@@ -1,26 +1,58 @@
 mod benchmark;
+mod commands;
 mod testcase;
 mod utils;
 
+use testcase::commitment::Commitment;
 use testcase::validator;
-use benchmark::test_runner;
-use utils::{file_manager, status};
-use std::fs::OpenOptions;
+use benchmark::{harness, limits, sandbox, test_runner};
+use benchmark::rusage::ResourceReport;
+use benchmark::test_runner::{BenchmarkParameters, BenchmarkResult, BenchmarkRun};
+use utils::config::{self, AnswerSource, BenchmarkHarness};
+use utils::{attestation, diff_table, file_manager, integrity, results, status};
+use utils::attestation::Attestation;
+use testcase::hints::Hint;
 use std::io;
 use std::fs;
 
 const TIMEOUT: u64 = 40;
-const CALIBRATION_TIMEOUT: u64 = 20;
+const SRC_TESTCASE_PATH: &str = "src/testcase.txt";
+const BENCHMARK_FILE_NAME: &str = "bench.json";
+const PARSED_BENCHMARK_FILE_NAME: &str = "bench_parsed.json";
+const PARTIAL_BENCHMARK_FILE_NAME: &str = "bench_partial.json";
 
-#[tokio::main]
-async fn main() -> io::Result<()> {
-    
-    // Check if main.py exists
-    let main_py_path = std::path::Path::new("src/main.py");
-    if !main_py_path.exists() {
-        status::write_status(false, "main.py does not exist").await?;
-        return Err(io::Error::new(io::ErrorKind::NotFound, "main.py does not exist"));
+fn main() -> io::Result<()> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+
+    // Entering the sandbox has to happen while the process is single-threaded
+    if args.first().map(String::as_str) == Some(sandbox::SANDBOX_COMMAND) {
+        sandbox::run(&args[1..]);
+    }
+
+    // Only a run of the submission consumes the key file. Verifying reads it
+    // and leaves it in place, and nothing else signs
+    match args.first().map(String::as_str) {
+        None => attestation::load_key(true)?,
+        Some("verify") => attestation::load_key(false)?,
+        Some(_) => attestation::forget_key(),
+    }
+
+    let result = tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()?
+        .block_on(run(args));
+    limits::restore_cgroup();
+    results::remove_private_dir();
+    result
+}
+
+async fn run(args: Vec<String>) -> io::Result<()> {
+
+    // Verifying needs neither a submission nor a level
+    if args.first().map(String::as_str) == Some("verify") {
+        return attestation::verify(args.get(1).map(String::as_str));
     }
+    let mut attestation = Attestation::new();
 
     // Parse level environment variable
     let level: f32 = match std::env::var("LEVEL")
@@ -29,25 +61,65 @@ async fn main() -> io::Result<()> {
     {
         Ok(val) => val,
         Err(e) => {
-            status::write_status(false, &format!("Failed to parse LEVEL env var: {}", e)).await?;
-            return Ok(());
+            return conclude(attestation, false, &format!("Failed to parse LEVEL env var: {}", e), &[]).await;
         }
     };
 
-    println!("Generating test case for level: {}", level);
+    let level_config = match config::load_level_config(level) {
+        Ok(config) => config,
+        Err(e) => {
+            return conclude(attestation, false, &format!("Failed to load level config: {}", e), &[]).await;
+        }
+    };
+
+    attestation.level = Some(level);
 
     // Calculate number of rows based on level
     let num_rows: usize = (level * 1_000_000.0) as usize;
-    
+
+    // Committing happens on the organizer's side, where there is no submission
+    if args.first().map(String::as_str) == Some("commit") {
+        return commands::commit::run(&level_config, num_rows).await;
+    }
+
+    // Check if main.py exists
+    let main_py_path = std::path::Path::new("src/main.py");
+    if !main_py_path.exists() {
+        conclude(attestation, false, "main.py does not exist", &[]).await?;
+        return Err(io::Error::new(io::ErrorKind::NotFound, "main.py does not exist"));
+    }
+
+    match args.first().map(String::as_str) {
+        None => {}
+        Some("minimize") => {
+            return commands::minimize::run(&level_config, TIMEOUT, args.get(1).map(String::as_str)).await;
+        }
+        Some("fuzz") => {
+            return commands::fuzz::run(&level_config, TIMEOUT, &args[1..]).await;
+        }
+        Some(command) => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Unknown command '{}', expected one of: minimize, fuzz, commit, verify", command),
+            ));
+        }
+    }
+
+    println!("Generating test case for level: {}", level);
+
     // Create output directory
     file_manager::ensure_output_dir()?;
 
-    // Find or create a test case
-    let testcase_id = match file_manager::find_or_create_testcase(num_rows).await {
+    // Find or create a test case. With a commitment the testcase has to be a
+    // distributed one, since solving it here would put the answer on disk
+    let testcase_result = match level_config.answer_source {
+        AnswerSource::Answer => file_manager::find_or_create_testcase(num_rows, level_config.collation).await,
+        AnswerSource::Commitment => file_manager::find_committed_testcase(num_rows),
+    };
+    let testcase_id = match testcase_result {
         Ok(id) => id,
         Err(e) => {
-            status::write_status(false, &format!("Failed to find or create testcase: {}", e)).await?;
-            return Ok(());
+            return conclude(attestation, false, &format!("Failed to find or create testcase: {}", e), &[]).await;
         }
     };
 
@@ -55,109 +127,250 @@ async fn main() -> io::Result<()> {
     let _ = match file_manager::copy_testcase_to_src_dir(num_rows, &testcase_id) {
         Ok(info) => info,
         Err(e) => {
-            status::write_status(false, &format!("Failed to copy testcase: {}", e)).await?;
-            return Ok(());
+            return conclude(attestation, false, &format!("Failed to copy testcase: {}", e), &[]).await;
         }
     };
 
-    // Read expected output file
-    let expected_output_file_path = format!(
-        "{}/answer_{}_{}.txt",
-        file_manager::TESTCASE_PATH,
-        num_rows,
-        testcase_id
-    );
+    // Fingerprint the input so a submission that rewrites it is caught
+    let testcase_fingerprint = match integrity::protect(SRC_TESTCASE_PATH) {
+        Ok(fingerprint) => fingerprint,
+        Err(e) => {
+            return conclude(attestation, false, &format!("Failed to protect testcase: {}", e), &[]).await;
+        }
+    };
+    attestation.testcase_sha256 = Some(integrity::to_hex(&testcase_fingerprint.sha256));
+
+    // Read expected output file, or the commitment standing in for it
+    let mut expected_output_lines = Vec::new();
+    let mut commitment = None;
+    match level_config.answer_source {
+        AnswerSource::Answer => {
+            let expected_output_file_path = file_manager::answer_path(num_rows, &testcase_id);
+            match file_manager::read_lines_from_file(&expected_output_file_path) {
+                Ok(lines) => {
+                    fs::remove_file(expected_output_file_path)?;
+                    expected_output_lines = lines;
+                },
+                Err(e) => {
+                    return conclude(attestation, false, &format!("Failed to read expected output: {}", e), &[]).await;
+                }
+            }
+        }
+        AnswerSource::Commitment => {
+            match Commitment::read(&file_manager::commitment_path(num_rows, &testcase_id)) {
+                Ok(c) => commitment = Some(c),
+                Err(e) => {
+                    return conclude(attestation, false, &format!("Failed to read answer commitment: {}", e), &[]).await;
+                }
+            }
+        }
+    }
 
-    let expected_output_lines = match file_manager::read_lines_from_file(&expected_output_file_path) {
-        Ok(lines) => {
-            fs::remove_file(expected_output_file_path)?;
-            lines
-        },
+    // Hash the submission as it stands before it runs
+    attestation.submission = match attestation::hash_submission("src", &["testcase.txt", &level_config.output_file]) {
+        Ok(hashes) => hashes,
         Err(e) => {
-            status::write_status(false, &format!("Failed to read expected output: {}", e)).await?;
-            return Ok(());
+            return conclude(attestation, false, &format!("Failed to hash submission: {}", e), &[]).await;
         }
     };
 
     // Run the Python solution
-    let test_result = test_runner::run_python_test(TIMEOUT).await?;
+    let run_options = match level_config.run_options(TIMEOUT) {
+        Ok(options) => options,
+        Err(e) => {
+            return conclude(attestation, false, &format!("Failed to prepare the test run: {}", e), &[]).await;
+        }
+    };
+    let submission_output = &run_options.output;
+    let test_result = match test_runner::run_python_test(&run_options).await {
+        Ok(result) => result,
+        Err(e) => {
+            return conclude(attestation, false, &format!("Failed to run the test: {}", e), &[]).await;
+        }
+    };
+    status::record_test_run(test_result.runtime, test_result.cpu_time);
+    if let Some(change) = testcase_fingerprint.changes(SRC_TESTCASE_PATH) {
+        return conclude(attestation, false, &format!("Input modified during the test run: {}", change), &[]).await;
+    }
     if !test_result.success {
-        status::write_status(false, &test_result.message).await?;
-        return Ok(());
+        return conclude(attestation, false, &test_result.message, &[]).await;
     }
 
-    let skip_calibration = test_result.runtime.is_none() || test_result.runtime.unwrap() >= CALIBRATION_TIMEOUT; 
+    let pyperf_options = level_config.pyperf_options();
+    let skip_calibration = match level_config.benchmark_harness {
+        BenchmarkHarness::Pyperf => {
+            !test_runner::should_calibrate(test_result.runtime, run_options.benchmark_budget_seconds, &pyperf_options)
+        }
+        // The native harness never calibrates
+        BenchmarkHarness::Native => true,
+    };
+    attestation.output_sha256 = integrity::FileFingerprint::of(submission_output.path())
+        .ok()
+        .map(|fingerprint| integrity::to_hex(&fingerprint.sha256));
 
     // Validate the output
-    let validation_result = validator::validate_output(&expected_output_lines, "src/output.txt")?;
+    let validation_options = level_config.validation_options();
+    let validation = match &commitment {
+        Some(commitment) => {
+            validator::validate_against_commitment(commitment, submission_output.path(), validation_options)
+        }
+        None => validator::validate_output(
+            &expected_output_lines,
+            submission_output.path(),
+            SRC_TESTCASE_PATH,
+            validation_options,
+        ),
+    };
+    let validation_result = match validation {
+        Ok(result) => result,
+        Err(e) => {
+            return conclude(attestation, false, &format!("Failed to validate output: {}", e), &[]).await;
+        }
+    };
+    if let Some(report) = &validation_result.report
+        && let Err(e) = validator::write_report(report)
+    {
+        eprintln!("Failed to write validation report: {}", e);
+    }
     if !validation_result.success {
-        status::write_status(false, &validation_result.message).await?;
-        return Err(io::Error::new(io::ErrorKind::Other, validation_result.message));
+        if diff_table::is_client_mode()
+            && let Some(report) = &validation_result.report
+        {
+            diff_table::print(report);
+        }
+        let hints = validation_result.report.as_ref().map(|report| report.hints.as_slice()).unwrap_or_default();
+        conclude(attestation, false, &validation_result.message, hints).await?;
+        return Err(io::Error::other(validation_result.message));
     }
 
-    if let Err(error) = fs::remove_file("src/output.txt") {
-        status::write_status(false, &format!("Failed to remove output file: {}", error)).await?;
-        return Ok(());
+    if let Err(error) = fs::remove_file(submission_output.path()) {
+        return conclude(attestation, false, &format!("Failed to remove output file: {}", error), &[]).await;
     }
 
     println!("{}", validation_result.message);
 
-    // Run benchmark
-    let benchmark_file_name: &str = "output/bench.json";
-    match test_runner::run_benchmark(benchmark_file_name, skip_calibration) {
-        Ok(_) => {},
+    // Run benchmark. pyperf writes into the daemon's private directory, and
+    // only the daemon copies results to where the worker collects them
+    let benchmark_path = results::private_path(BENCHMARK_FILE_NAME);
+    let benchmark_run = match level_config.benchmark_harness {
+        BenchmarkHarness::Pyperf => {
+            test_runner::run_benchmark(
+                &benchmark_path,
+                skip_calibration,
+                &pyperf_options,
+                &run_options,
+                SRC_TESTCASE_PATH,
+                &testcase_fingerprint,
+            )
+            .await
+        }
+        BenchmarkHarness::Native => {
+            harness::run(
+                &run_options,
+                level_config.benchmark_warmups,
+                level_config.benchmark_runs,
+                SRC_TESTCASE_PATH,
+                &testcase_fingerprint,
+            )
+            .await
+        }
+    };
+    let benchmark_result = match benchmark_run {
+        Ok(BenchmarkRun::Finished(result)) => result,
+        Ok(BenchmarkRun::Stopped { reason, values }) => {
+            let partial = serde_json::json!({ "reason": reason, "values": values });
+            results::write_artifact(PARTIAL_BENCHMARK_FILE_NAME, &serde_json::to_vec_pretty(&partial)?)?;
+            let collected = match values.len() {
+                0 => "No values were collected before it was stopped".to_string(),
+                count => format!(
+                    "Collected {} values before it was stopped: {}",
+                    count,
+                    values.iter().map(|value| format!("{:.3} ms", value * 1000.0)).collect::<Vec<_>>().join(", ")
+                ),
+            };
+            let message = format!("{}. {}", reason, collected);
+            return conclude(attestation, false, &message, &[]).await;
+        }
         Err(e) => {
-            status::write_status(false, &format!("Failed to run benchmark: {}", e)).await?;
-            return Ok(());
+            return conclude(attestation, false, &format!("Failed to run benchmark: {}", e), &[]).await;
         }
+    };
+
+    if let Some(change) = testcase_fingerprint.changes(SRC_TESTCASE_PATH) {
+        return conclude(attestation, false, &format!("Input modified during the benchmark: {}", change), &[]).await;
     }
 
-    // Parse benchmark results
-    println!("Fetching benchmark output...");
-    let benchmark_output = match fs::File::open(benchmark_file_name) {
-        Ok(file) => {
-            let reader = io::BufReader::new(file);
-            match serde_json::from_reader(reader) {
-                Ok(b) => b,
+    let parsed_benchmark = match benchmark_result {
+        BenchmarkResult::Pyperf(benchmark_usage) => {
+            // Parse benchmark results
+            println!("Fetching benchmark output...");
+            let benchmark_output = match fs::read(&benchmark_path) {
+                Ok(contents) => {
+                    match serde_json::from_slice(&contents) {
+                        Ok(b) => {
+                            results::write_artifact(BENCHMARK_FILE_NAME, &contents)?;
+                            b
+                        },
+                        Err(e) => {
+                            return conclude(attestation, false, &format!("Failed to parse benchmark output: {}", e), &[]).await;
+                        }
+                    }
+                },
                 Err(e) => {
-                    status::write_status(false, &format!("Failed to parse benchmark output: {}", e)).await?;
-                    return Ok(());
+                    return conclude(attestation, false, &format!("Failed to open benchmark file: {}", e), &[]).await;
+                }
+            };
+
+            println!("Got benchmark output!\nParsing benchmark...");
+            let resource_usage = ResourceReport {
+                test_run: test_result.usage,
+                benchmark: Some(benchmark_usage),
+            };
+            let parameters = BenchmarkParameters::Pyperf {
+                options: pyperf_options,
+                calibrated: !skip_calibration,
+            };
+            match benchmark::parser::parse(benchmark_output, skip_calibration, resource_usage, parameters) {
+                Ok(p) => p,
+                Err(e) => {
+                    return conclude(attestation, false, &format!("Failed to parse benchmark: {}", e), &[]).await;
                 }
             }
-        },
-        Err(e) => {
-            status::write_status(false, &format!("Failed to open benchmark file: {}", e)).await?;
-            return Ok(());
         }
-    };
-
-    println!("Got benchmark output!\nParsing benchmark...");
-    let parsed_benchmark = match benchmark::parser::parse(benchmark_output, skip_calibration) {
-        Ok(p) => p,
-        Err(e) => {
-            status::write_status(false, &format!("Failed to parse benchmark: {}", e)).await?;
-            return Ok(());
+        BenchmarkResult::Native(native) => {
+            let raw = native.raw();
+            results::write_artifact(BENCHMARK_FILE_NAME, &serde_json::to_vec_pretty(&raw)?)?;
+            (native.stats(test_result.usage), raw)
         }
     };
 
-    print!("Average runtime: {:.6} ms\n", parsed_benchmark.0.get_mean() / 1000.0);
+    println!("Average runtime: {:.6} ms", parsed_benchmark.0.get_mean() / 1000.0);
 
     // Write parsed benchmark to file
-    let bench_parsed_path: &str = "output/bench_parsed.json";
-    let benchmark_output_file = OpenOptions::new()
-        .write(true)
-        .create(true)
-        .truncate(true)
-        .open(bench_parsed_path)?;
-
-    let benchmark_output_writer = io::BufWriter::new(benchmark_output_file);
-    if let Err(e) = serde_json::to_writer_pretty(benchmark_output_writer, &parsed_benchmark) {
-        status::write_status(false, &format!("Failed to write benchmark results: {}", e)).await?;
-        return Ok(());
+    let parsed_contents = serde_json::to_vec_pretty(&parsed_benchmark)?;
+    if let Err(e) = results::write_artifact(PARSED_BENCHMARK_FILE_NAME, &parsed_contents) {
+        return conclude(attestation, false, &format!("Failed to write benchmark results: {}", e), &[]).await;
     }
 
     println!("Parsed benchmark written to file!");
-    status::write_status(true, "Testing and benchmarking completed successfully").await?;
+    let completion_message = match &validation_result.report {
+        Some(report) if report.total_differences() > 0 => format!(
+            "Testing and benchmarking completed successfully. {}",
+            validation_result.message
+        ),
+        _ => "Testing and benchmarking completed successfully".to_string(),
+    };
+    conclude(attestation, true, &completion_message, &[]).await
+}
 
-    Ok(())
+/// Writes the verdict to the status file and signs the attestation over it.
+/// Every exit of a run goes through here, since the worker only trusts a
+/// status the attestation vouches for.
+async fn conclude(attestation: Attestation, success: bool, message: &str, hints: &[Hint]) -> io::Result<()> {
+    if hints.is_empty() {
+        status::write_status(success, message).await?;
+    } else {
+        status::write_status_with_hints(success, message, hints).await?;
+    }
+    attestation.finish(success, message)
 }
\ No newline at end of file
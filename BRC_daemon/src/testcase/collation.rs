@@ -0,0 +1,55 @@
+use std::cmp::Ordering;
+use std::fmt;
+use serde::{Deserialize, Serialize};
+
+/// The rule stations are sorted by, in both the reference answer and the
+/// submission's output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Collation {
+    /// Compare the UTF-8 bytes of the names, like Rust's `str` ordering.
+    #[default]
+    ByteOrder,
+    /// Compare Unicode code points, like Python's `sorted()` on `str`. For
+    /// valid UTF-8 this always agrees with byte order.
+    CodePoint,
+    /// Compare lowercased names, falling back to byte order on ties so the
+    /// order stays total.
+    CaseInsensitive,
+}
+
+impl Collation {
+    pub fn compare(&self, a: &str, b: &str) -> Ordering {
+        match self {
+            Collation::ByteOrder => a.as_bytes().cmp(b.as_bytes()),
+            Collation::CodePoint => a.chars().cmp(b.chars()),
+            Collation::CaseInsensitive => a
+                .chars()
+                .flat_map(char::to_lowercase)
+                .cmp(b.chars().flat_map(char::to_lowercase))
+                .then_with(|| a.as_bytes().cmp(b.as_bytes())),
+        }
+    }
+
+    pub fn sort(&self, names: &mut [String]) {
+        names.sort_by(|a, b| self.compare(a, b));
+    }
+
+    pub fn is_sorted(&self, names: &[&str]) -> bool {
+        names
+            .windows(2)
+            .all(|pair| self.compare(pair[0], pair[1]) != Ordering::Greater)
+    }
+}
+
+impl fmt::Display for Collation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Collation::ByteOrder => write!(f, "byte order (comparing the UTF-8 bytes of each name)"),
+            Collation::CodePoint => write!(f, "Unicode code point order (like Python's sorted() on str)"),
+            Collation::CaseInsensitive => {
+                write!(f, "case-insensitive order (lowercased names, ties broken by byte order)")
+            }
+        }
+    }
+}
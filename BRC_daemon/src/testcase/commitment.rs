@@ -0,0 +1,150 @@
+use std::fs;
+use std::io;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use crate::testcase::format::OutputFormat;
+use crate::testcase::validator::{StationRecord, Strictness};
+use crate::utils::integrity::{from_hex, to_hex};
+
+const SALT_LENGTH: usize = 16;
+const TOLERANCE: f64 = 1e-6;
+
+/// A salted hash of each station's reference values, in reference order.
+/// Station names are in the clear since they can be read off the testcase.
+///
+/// This keeps the answer from sitting on disk in readable form, but it does
+/// not keep it secret. The salt ships in the same file, and a station's values
+/// are three one-decimal numbers: with generated data min and max sit near
+/// the ends of the range and the mean near zero, so each digest falls to a
+/// few thousand guesses. It stops casual peeking, not a determined contestant.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Commitment {
+    pub salt: String,
+    pub stations: Vec<CommittedStation>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CommittedStation {
+    pub station: String,
+    pub digest: String,
+}
+
+impl Commitment {
+    /// Commits to a reference answer, one `city=min/mean/max` line per station.
+    pub fn new(answer_lines: &[String]) -> io::Result<Self> {
+        let salt: [u8; SALT_LENGTH] = rand::random();
+        let mut stations = Vec::with_capacity(answer_lines.len());
+
+        for line in answer_lines.iter().filter(|line| !line.trim().is_empty()) {
+            let record = OutputFormat::Lines
+                .parse_entry(line, Strictness::Lenient)
+                .map_err(|e| io::Error::other(format!("Invalid answer line '{}': {:?}", line, e)))?;
+            let digest = digest(&salt, &record)
+                .ok_or_else(|| io::Error::other(format!("Answer line '{}' is not in tenths", line)))?;
+            stations.push(CommittedStation {
+                station: record.name,
+                digest,
+            });
+        }
+
+        Ok(Commitment {
+            salt: to_hex(&salt),
+            stations,
+        })
+    }
+
+    pub fn read(path: &str) -> io::Result<Self> {
+        let contents = fs::read_to_string(path)?;
+        let commitment: Commitment = serde_json::from_str(&contents)
+            .map_err(|e| io::Error::other(format!("Invalid commitment file {}: {}", path, e)))?;
+        from_hex(&commitment.salt)
+            .ok_or_else(|| io::Error::other(format!("Invalid salt in commitment file {}", path)))?;
+        Ok(commitment)
+    }
+
+    pub fn write(&self, path: &str) -> io::Result<()> {
+        fs::write(path, serde_json::to_string_pretty(self)?)
+    }
+
+    /// Whether `record` has exactly the values committed for the station at `position`.
+    pub fn matches(&self, position: usize, record: &StationRecord) -> bool {
+        let Some(committed) = self.stations.get(position) else {
+            return false;
+        };
+        let salt = from_hex(&self.salt).unwrap_or_default();
+        committed.station == record.name && digest(&salt, record).as_deref() == Some(committed.digest.as_str())
+    }
+}
+
+/// Hashes a record's values in their canonical one-decimal spelling. Values
+/// that aren't whole tenths can't match any reference and give `None`.
+fn digest(salt: &[u8], record: &StationRecord) -> Option<String> {
+    let mut canonical = Vec::with_capacity(3);
+    for value in [record.min, record.mean, record.max] {
+        let tenths = (value * 10.0).round();
+        if (value * 10.0 - tenths).abs() > TOLERANCE * 10.0 {
+            return None;
+        }
+        // Adding zero turns -0.0 into 0.0, which the validator treats as equal
+        canonical.push(format!("{:.1}", tenths / 10.0 + 0.0));
+    }
+
+    let mut hasher = Sha256::new();
+    hasher.update(salt);
+    hasher.update(record.name.as_bytes());
+    hasher.update([0]);
+    hasher.update(canonical.join("/").as_bytes());
+    Some(to_hex(&hasher.finalize()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(name: &str, min: f64, mean: f64, max: f64) -> StationRecord {
+        StationRecord {
+            name: name.to_string(),
+            min,
+            mean,
+            max,
+        }
+    }
+
+    fn commitment() -> Commitment {
+        Commitment::new(&["Abha=-23.0/18.0/59.2".to_string(), "Zyx=-0.0/0.0/0.1".to_string()]).unwrap()
+    }
+
+    #[test]
+    fn committed_values_match() {
+        let commitment = commitment();
+        assert!(commitment.matches(0, &record("Abha", -23.0, 18.0, 59.2)));
+        assert!(commitment.matches(1, &record("Zyx", -0.0, 0.0, 0.1)));
+    }
+
+    #[test]
+    fn negative_zero_matches_zero() {
+        assert!(commitment().matches(1, &record("Zyx", 0.0, -0.0, 0.1)));
+    }
+
+    #[test]
+    fn wrong_value_station_or_position_does_not_match() {
+        let commitment = commitment();
+        assert!(!commitment.matches(0, &record("Abha", -23.0, 18.1, 59.2)));
+        assert!(!commitment.matches(0, &record("Abha", -23.0, 18.05, 59.2)));
+        assert!(!commitment.matches(0, &record("Zyx", -23.0, 18.0, 59.2)));
+        assert!(!commitment.matches(1, &record("Abha", -23.0, 18.0, 59.2)));
+        assert!(!commitment.matches(2, &record("Abha", -23.0, 18.0, 59.2)));
+    }
+
+    #[test]
+    fn commitment_survives_a_round_trip_through_its_file() {
+        let path = std::env::temp_dir().join(format!("brc-commitment-test-{}.json", uuid::Uuid::new_v4()));
+        let path = path.to_str().unwrap();
+        commitment().write(path).unwrap();
+        let read = Commitment::read(path);
+        fs::remove_file(path).unwrap();
+        assert!(read.unwrap().matches(0, &record("Abha", -23.0, 18.0, 59.2)));
+    }
+}
@@ -0,0 +1,241 @@
+use std::fmt;
+use serde::{Deserialize, Serialize};
+use serde_json::value::RawValue;
+use crate::testcase::validator::{parse_value, Field, ParseErrorKind, StationRecord, Strictness};
+
+const CSV_HEADER: &str = "station,min,mean,max";
+
+/// The layout a submission writes its results in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OutputFormat {
+    /// `Abha=-23.0/18.0/59.2`, one station per line.
+    #[default]
+    Lines,
+    /// The original 1BRC `{Abha=-23.0/18.0/59.2, Abidjan=-16.2/26.0/67.3}` on a single line.
+    Braces,
+    /// One `{"station":"Abha","min":-23.0,"mean":18.0,"max":59.2}` object per
+    /// line. Any JSON spelling of it parses, but byte-exact validation wants
+    /// exactly this one, in this key order and without spaces.
+    Json,
+    /// A `station,min,mean,max` header followed by `Abha,-23.0,18.0,59.2` rows.
+    Csv,
+}
+
+impl fmt::Display for OutputFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OutputFormat::Lines => write!(f, "lines"),
+            OutputFormat::Braces => write!(f, "braces"),
+            OutputFormat::Json => write!(f, "json"),
+            OutputFormat::Csv => write!(f, "csv"),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct JsonStation<'a> {
+    station: String,
+    #[serde(borrow)]
+    min: &'a RawValue,
+    #[serde(borrow)]
+    mean: &'a RawValue,
+    #[serde(borrow)]
+    max: &'a RawValue,
+}
+
+#[derive(Serialize)]
+struct JsonStationOut<'a> {
+    station: &'a str,
+    min: f64,
+    mean: f64,
+    max: f64,
+}
+
+impl OutputFormat {
+    /// Splits a non-blank output line into the station entries it holds.
+    /// `first` marks the first non-blank line, where CSV expects its header.
+    pub fn split_line<'a>(&self, line: &'a str, first: bool) -> Result<Vec<&'a str>, ParseErrorKind> {
+        match self {
+            OutputFormat::Lines | OutputFormat::Json => Ok(vec![line]),
+            OutputFormat::Braces => {
+                let inner = line
+                    .strip_prefix('{')
+                    .and_then(|rest| rest.strip_suffix('}'))
+                    .ok_or(ParseErrorKind::MissingBraces)?;
+                if inner.is_empty() {
+                    return Ok(Vec::new());
+                }
+                Ok(inner.split(',').map(|entry| entry.strip_prefix(' ').unwrap_or(entry)).collect())
+            }
+            OutputFormat::Csv if first => {
+                if line == CSV_HEADER {
+                    Ok(Vec::new())
+                } else {
+                    Err(ParseErrorKind::MissingHeader {
+                        expected: CSV_HEADER.to_string(),
+                    })
+                }
+            }
+            OutputFormat::Csv => Ok(vec![line]),
+        }
+    }
+
+    pub fn parse_entry(&self, entry: &str, strictness: Strictness) -> Result<StationRecord, ParseErrorKind> {
+        let (name, values) = match self {
+            OutputFormat::Lines | OutputFormat::Braces => {
+                let (name, values) = entry
+                    .split_once('=')
+                    .ok_or(ParseErrorKind::MissingSeparator)?;
+                (name, values.split('/').collect::<Vec<_>>())
+            }
+            OutputFormat::Csv => {
+                // Split from the right so a comma inside a station name survives
+                let mut parts: Vec<&str> = entry.rsplitn(4, ',').collect();
+                parts.reverse();
+                if parts.len() != 4 {
+                    return Err(ParseErrorKind::ValueCount { found: parts.len().saturating_sub(1) });
+                }
+                (parts[0], parts[1..].to_vec())
+            }
+            OutputFormat::Json => {
+                let station: JsonStation = serde_json::from_str(entry)
+                    .map_err(|e| ParseErrorKind::InvalidJson { message: e.to_string() })?;
+                if station.station.is_empty() {
+                    return Err(ParseErrorKind::EmptyStation);
+                }
+                return Ok(StationRecord {
+                    min: parse_value(Field::Min, station.min.get(), strictness)?,
+                    mean: parse_value(Field::Mean, station.mean.get(), strictness)?,
+                    max: parse_value(Field::Max, station.max.get(), strictness)?,
+                    name: station.station,
+                });
+            }
+        };
+
+        if name.is_empty() {
+            return Err(ParseErrorKind::EmptyStation);
+        }
+        if values.len() != 3 {
+            return Err(ParseErrorKind::ValueCount { found: values.len() });
+        }
+
+        Ok(StationRecord {
+            name: name.to_string(),
+            min: parse_value(Field::Min, values[0], strictness)?,
+            mean: parse_value(Field::Mean, values[1], strictness)?,
+            max: parse_value(Field::Max, values[2], strictness)?,
+        })
+    }
+
+    /// Renders records the way a byte-exact submission in this format has to look.
+    pub fn render(&self, records: &[StationRecord]) -> Vec<String> {
+        match self {
+            OutputFormat::Lines => records
+                .iter()
+                .map(|r| format!("{}={:.1}/{:.1}/{:.1}", r.name, r.min, r.mean, r.max))
+                .collect(),
+            OutputFormat::Braces => {
+                let entries: Vec<String> = records
+                    .iter()
+                    .map(|r| format!("{}={:.1}/{:.1}/{:.1}", r.name, r.min, r.mean, r.max))
+                    .collect();
+                vec![format!("{{{}}}", entries.join(", "))]
+            }
+            OutputFormat::Json => records
+                .iter()
+                .map(|r| {
+                    serde_json::to_string(&JsonStationOut {
+                        station: &r.name,
+                        min: r.min,
+                        mean: r.mean,
+                        max: r.max,
+                    })
+                    .unwrap_or_default()
+                })
+                .collect(),
+            OutputFormat::Csv => std::iter::once(CSV_HEADER.to_string())
+                .chain(
+                    records
+                        .iter()
+                        .map(|r| format!("{},{:.1},{:.1},{:.1}", r.name, r.min, r.mean, r.max)),
+                )
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(name: &str, min: f64, mean: f64, max: f64) -> StationRecord {
+        StationRecord {
+            name: name.to_string(),
+            min,
+            mean,
+            max,
+        }
+    }
+
+    fn entries(format: OutputFormat, lines: &[&str]) -> Result<Vec<StationRecord>, ParseErrorKind> {
+        let mut records = Vec::new();
+        for (i, line) in lines.iter().enumerate() {
+            for entry in format.split_line(line, i == 0)? {
+                records.push(format.parse_entry(entry, Strictness::Canonical)?);
+            }
+        }
+        Ok(records)
+    }
+
+    #[test]
+    fn braces_split_into_entries() {
+        let records = entries(OutputFormat::Braces, &["{Abha=-23.0/18.0/59.2, Abidjan=-16.2/26.0/67.3}"]).unwrap();
+        let names: Vec<&str> = records.iter().map(|record| record.name.as_str()).collect();
+        assert_eq!(names, ["Abha", "Abidjan"]);
+        assert_eq!(records[1].max, 67.3);
+        assert!(entries(OutputFormat::Braces, &["{}"]).unwrap().is_empty());
+        assert!(matches!(
+            entries(OutputFormat::Braces, &["Abha=-23.0/18.0/59.2"]),
+            Err(ParseErrorKind::MissingBraces)
+        ));
+    }
+
+    #[test]
+    fn csv_keeps_commas_in_station_names_and_needs_its_header() {
+        let records = entries(OutputFormat::Csv, &[CSV_HEADER, "Washington, D.C.,-1.0,14.6,38.9"]).unwrap();
+        assert_eq!(records[0].name, "Washington, D.C.");
+        assert_eq!((records[0].min, records[0].mean, records[0].max), (-1.0, 14.6, 38.9));
+        assert!(matches!(
+            entries(OutputFormat::Csv, &["Abha,-23.0,18.0,59.2"]),
+            Err(ParseErrorKind::MissingHeader { .. })
+        ));
+        assert!(matches!(
+            entries(OutputFormat::Csv, &[CSV_HEADER, "Abha,-23.0,18.0"]),
+            Err(ParseErrorKind::ValueCount { found: 2 })
+        ));
+    }
+
+    #[test]
+    fn json_accepts_any_spacing_but_no_extra_fields() {
+        let spaced = r#"{"station": "Abha", "min": -23.0, "mean": 18.0, "max": 59.2}"#;
+        assert_eq!(entries(OutputFormat::Json, &[spaced]).unwrap()[0].name, "Abha");
+        let extra = r#"{"station":"Abha","min":-23.0,"mean":18.0,"max":59.2,"count":3}"#;
+        assert!(matches!(
+            entries(OutputFormat::Json, &[extra]),
+            Err(ParseErrorKind::InvalidJson { .. })
+        ));
+        let bare = r#"{"station":"Abha","min":-23,"mean":18.0,"max":59.2}"#;
+        assert!(matches!(
+            entries(OutputFormat::Json, &[bare]),
+            Err(ParseErrorKind::NonCanonicalValue { field: Field::Min, .. })
+        ));
+    }
+
+    #[test]
+    fn json_renders_the_documented_spelling() {
+        let rendered = OutputFormat::Json.render(&[record("Abha", -23.0, 18.0, 59.2)]);
+        assert_eq!(rendered, [r#"{"station":"Abha","min":-23.0,"mean":18.0,"max":59.2}"#]);
+    }
+}
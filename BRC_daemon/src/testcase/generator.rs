@@ -4,7 +4,7 @@ use std::{
     sync::Arc,
     time::Instant,
 };
-use rand::{thread_rng, Rng};
+use rand::{rngs::StdRng, seq::SliceRandom, thread_rng, Rng, SeedableRng};
 use tokio::sync::mpsc;
 use uuid::Uuid;
 
@@ -32,7 +32,14 @@ const CITIES: [&str; 216] = [
     "Begusarai", "Katihar", "Siwan", "Gopalganj", "Samastipur", "Darbhanga", "Sasaram", "Hazaribagh", "Giridih", "Daltonganj", "Chutia"
 ];  
 
+// Names that trip up sorting, prefix matching and UTF-8 handling
+const EDGE_CASE_CITIES: [&str; 14] = [
+    "Kot", "Kota", "Kotha", "Abha", "abha", "Zürich", "Ürümqi", "São Paulo", "Ōsaka", "İzmir",
+    "Kraków", "a Coruña", "Ağrı", "Zwolle",
+];
+
 const CHUNK_SIZE: usize = 1_000_000;
+const MAX_SMALL_ROWS: usize = 200;
 const BUFFER_SIZE: usize = 8 * 1024 * 1024;
 const NUM_WORKERS: usize = 10;
 
@@ -108,8 +115,7 @@ pub async fn generate_testcase(num_rows: usize) -> std::io::Result<String> {
     let testcases_dir = "testcases";
     if let Err(e) = std::fs::create_dir_all(testcases_dir) {
         eprintln!("Failed to create testcases directory: {}", e);
-        return Err(std::io::Error::new(
-            std::io::ErrorKind::Other,
+        return Err(std::io::Error::other(
             format!("Failed to create testcases directory: {}", e),
         ));
     }
@@ -157,7 +163,7 @@ pub async fn generate_testcase(num_rows: usize) -> std::io::Result<String> {
         Ok(result) => result?,
         Err(e) => {
             eprintln!("Writer task failed: {}", e);
-            return Err(std::io::Error::new(std::io::ErrorKind::Other, e.to_string()));
+            return Err(std::io::Error::other(e.to_string()));
         }
     }
 
@@ -177,4 +183,61 @@ pub async fn generate_testcase(num_rows: usize) -> std::io::Result<String> {
 
     timer.elapsed();
     Ok(output_file)
-}
\ No newline at end of file
+}
+
+/// Shapes of small testcase used by `daemon fuzz`.
+#[derive(Debug, Clone, Copy)]
+pub enum Profile {
+    Uniform,
+    SingleStation,
+    Extremes,
+    Rounding,
+    TrickyNames,
+}
+
+impl Profile {
+    const ALL: [Profile; 5] = [
+        Profile::Uniform,
+        Profile::SingleStation,
+        Profile::Extremes,
+        Profile::Rounding,
+        Profile::TrickyNames,
+    ];
+}
+
+/// Generates a small testcase that depends only on `seed`, so a failing
+/// seed can be replayed exactly.
+pub fn generate_small_testcase(seed: u64) -> (Profile, Vec<String>) {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let profile = Profile::ALL[(seed % Profile::ALL.len() as u64) as usize];
+    let num_rows = rng.gen_range(1..=MAX_SMALL_ROWS);
+    let single_city = CITIES[rng.gen_range(0..CITIES.len())];
+
+    let lines = (0..num_rows)
+        .map(|_| {
+            let (city, temp) = match profile {
+                Profile::Uniform => (
+                    CITIES[rng.gen_range(0..CITIES.len())],
+                    format!("{:.1}", rng.gen_range(-999..=999) as f64 / 10.0),
+                ),
+                Profile::SingleStation => (single_city, format!("{:.1}", rng.gen_range(-999..=999) as f64 / 10.0)),
+                Profile::Extremes => (
+                    CITIES[rng.gen_range(0..4)],
+                    ["-99.9", "99.9", "-0.1", "0.1", "0.0", "-0.0"].choose(&mut rng).unwrap().to_string(),
+                ),
+                // Few rows per station with values that land means on .x5 boundaries
+                Profile::Rounding => (
+                    CITIES[rng.gen_range(0..8)],
+                    format!("{:.1}", rng.gen_range(-3..=3) as f64 / 10.0),
+                ),
+                Profile::TrickyNames => (
+                    *EDGE_CASE_CITIES.choose(&mut rng).unwrap(),
+                    format!("{:.1}", rng.gen_range(-999..=999) as f64 / 10.0),
+                ),
+            };
+            format!("{};{}", city, temp)
+        })
+        .collect();
+
+    (profile, lines)
+}
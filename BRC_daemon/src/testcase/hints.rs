@@ -0,0 +1,241 @@
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
+use serde::Serialize;
+use crate::testcase::collation::Collation;
+use crate::testcase::validator::{ParseErrorKind, StationRecord, ValidationReport};
+
+const TOLERANCE: f64 = 1e-6;
+const MAX_TRUNCATION_CHECKS: usize = 1000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HintKind {
+    MeanRounding,
+    SortOrder,
+    TruncatedNames,
+    SkippedLastLine,
+}
+
+/// A guess at why a submission failed, based on the shape of its mistakes.
+#[derive(Debug, Serialize)]
+pub struct Hint {
+    pub kind: HintKind,
+    pub message: String,
+}
+
+impl Hint {
+    fn new(kind: HintKind, message: String) -> Self {
+        Hint { kind, message }
+    }
+}
+
+/// Classifies the failure patterns we kept answering by hand. `last_input_station`
+/// is the station on the final line of the testcase, when it could be read.
+pub fn diagnose(
+    expected: &[StationRecord],
+    actual: &[StationRecord],
+    report: &ValidationReport,
+    last_input_station: Option<&str>,
+) -> Vec<Hint> {
+    let mut actual_by_name: HashMap<&str, &StationRecord> = HashMap::with_capacity(actual.len());
+    for record in actual {
+        actual_by_name.entry(record.name.as_str()).or_insert(record);
+    }
+
+    let mut hints = Vec::new();
+    hints.extend(mean_rounding(expected, &actual_by_name));
+    if report.out_of_order > 0 {
+        hints.extend(sort_order(expected, actual, report.collation));
+    }
+    hints.extend(truncated_names(expected, &actual_by_name, report));
+    if let Some(station) = last_input_station {
+        hints.extend(skipped_last_line(expected, &actual_by_name, report, station));
+    }
+    hints
+}
+
+fn values_match(a: f64, b: f64) -> bool {
+    (a - b).abs() <= TOLERANCE
+}
+
+fn mean_rounding(expected: &[StationRecord], actual_by_name: &HashMap<&str, &StationRecord>) -> Option<Hint> {
+    let mut wrong_means = 0;
+    let mut tenth_above = 0;
+    let mut tenth_below = 0;
+
+    for expected_record in expected {
+        let Some(actual_record) = actual_by_name.get(expected_record.name.as_str()) else {
+            continue;
+        };
+        let difference = actual_record.mean - expected_record.mean;
+        if values_match(difference, 0.0) {
+            continue;
+        }
+
+        wrong_means += 1;
+        if values_match(difference, 0.1) {
+            tenth_above += 1;
+        } else if values_match(difference, -0.1) {
+            tenth_below += 1;
+        }
+    }
+
+    if wrong_means == 0 || tenth_above + tenth_below != wrong_means {
+        return None;
+    }
+
+    let message = if tenth_below == wrong_means {
+        format!(
+            "All {} wrong means are exactly 0.1 below the reference. Means are rounded up to one \
+             decimal place (ceiling of the exact mean), so round() or truncation gives these answers.",
+            wrong_means
+        )
+    } else if tenth_above == wrong_means {
+        format!(
+            "All {} wrong means are exactly 0.1 above the reference. Means are rounded up to one \
+             decimal place from the exact value; floating-point error before the ceiling, or \
+             rounding negative means away from zero, pushes them one step too far.",
+            wrong_means
+        )
+    } else {
+        format!(
+            "All {} wrong means are off by exactly 0.1 in one direction or the other. \
+             Check the rounding rule: means are rounded up (ceiling) to one decimal place.",
+            wrong_means
+        )
+    };
+
+    Some(Hint::new(HintKind::MeanRounding, message))
+}
+
+fn is_sorted_by_key<K: Ord>(names: &[&str], key: impl Fn(&str) -> K) -> bool {
+    names.windows(2).all(|pair| key(pair[0]) <= key(pair[1]))
+}
+
+fn sort_order(expected: &[StationRecord], actual: &[StationRecord], collation: Collation) -> Option<Hint> {
+    let expected_names: HashSet<&str> = expected.iter().map(|record| record.name.as_str()).collect();
+    let mut seen = HashSet::new();
+    let names: Vec<&str> = actual
+        .iter()
+        .map(|record| record.name.as_str())
+        .filter(|name| expected_names.contains(name) && seen.insert(*name))
+        .collect();
+
+    let required = format!("stations must be sorted by {}", collation);
+    let reversed = names
+        .windows(2)
+        .all(|pair| collation.compare(pair[0], pair[1]) != Ordering::Less);
+
+    let description = if reversed {
+        "in reverse order"
+    } else if collation != Collation::ByteOrder && Collation::ByteOrder.is_sorted(&names) {
+        "by byte order"
+    } else if collation != Collation::CaseInsensitive && Collation::CaseInsensitive.is_sorted(&names) {
+        "case-insensitively"
+    } else if is_sorted_by_key(&names, |name| {
+        name.chars()
+            .filter(|c| c.is_alphanumeric())
+            .flat_map(char::to_lowercase)
+            .collect::<String>()
+    }) {
+        "by locale collation (ignoring case and punctuation)"
+    } else {
+        return Some(Hint::new(
+            HintKind::SortOrder,
+            format!("Your stations are not sorted, but {}.", required),
+        ));
+    };
+
+    Some(Hint::new(
+        HintKind::SortOrder,
+        format!("Your stations are sorted {}, but {}.", description, required),
+    ))
+}
+
+fn truncated_names(
+    expected: &[StationRecord],
+    actual_by_name: &HashMap<&str, &StationRecord>,
+    report: &ValidationReport,
+) -> Option<Hint> {
+    let invalid_utf8 = report
+        .parse_errors
+        .iter()
+        .any(|error| matches!(error.kind, ParseErrorKind::InvalidUtf8));
+    if invalid_utf8 {
+        return Some(Hint::new(
+            HintKind::TruncatedNames,
+            "Some output lines are not valid UTF-8, which usually means station names were cut in \
+             the middle of a multi-byte character. Split lines on bytes before decoding, never \
+             decode a fixed-size chunk on its own."
+                .to_string(),
+        ));
+    }
+
+    let missing: Vec<&str> = expected
+        .iter()
+        .map(|record| record.name.as_str())
+        .filter(|name| !actual_by_name.contains_key(name))
+        .collect();
+    if missing.is_empty() {
+        return None;
+    }
+
+    let expected_names: HashSet<&str> = expected.iter().map(|record| record.name.as_str()).collect();
+    let truncated = actual_by_name
+        .keys()
+        .filter(|name| !expected_names.contains(*name))
+        .take(MAX_TRUNCATION_CHECKS)
+        .find_map(|name| {
+            let stem = name.trim_end_matches('\u{FFFD}');
+            missing
+                .iter()
+                .find(|full| full.len() > stem.len() && full.starts_with(stem))
+                .map(|full| (*name, *full))
+        })?;
+
+    Some(Hint::new(
+        HintKind::TruncatedNames,
+        format!(
+            "Station '{}' looks like a truncated '{}'. Check that names aren't cut at a chunk or \
+             multi-byte character boundary.",
+            truncated.0, truncated.1
+        ),
+    ))
+}
+
+fn skipped_last_line(
+    expected: &[StationRecord],
+    actual_by_name: &HashMap<&str, &StationRecord>,
+    report: &ValidationReport,
+    last_input_station: &str,
+) -> Option<Hint> {
+    if report.extra > 0 || report.duplicate > 0 {
+        return None;
+    }
+
+    let mut wrong_stations = expected.iter().filter(|expected_record| {
+        match actual_by_name.get(expected_record.name.as_str()) {
+            None => true,
+            Some(actual_record) => {
+                !values_match(actual_record.min, expected_record.min)
+                    || !values_match(actual_record.mean, expected_record.mean)
+                    || !values_match(actual_record.max, expected_record.max)
+            }
+        }
+    });
+
+    let only_wrong = wrong_stations.next()?;
+    if wrong_stations.next().is_some() || only_wrong.name != last_input_station {
+        return None;
+    }
+
+    Some(Hint::new(
+        HintKind::SkippedLastLine,
+        format!(
+            "Only {} is wrong, and it is the station on the last line of the input. The last line \
+             is probably being skipped, e.g. because the file has no trailing newline or the read \
+             loop stops one line early.",
+            last_input_station
+        ),
+    ))
+}
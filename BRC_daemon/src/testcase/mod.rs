@@ -1,3 +1,7 @@
+pub mod collation;
+pub mod commitment;
+pub mod format;
 pub mod generator;
+pub mod hints;
 pub mod solver;
 pub mod validator;
\ No newline at end of file
@@ -7,9 +7,13 @@ use std::{
     time::Instant,
 };
 
+use crate::testcase::collation::Collation;
+
 const NUM_WORKERS: usize = 10;
 
-pub fn solve_testcase(input_file: &str) -> std::io::Result<()> {
+type SharedResults = Arc<Mutex<HashMap<String, (f32, f64, f32, usize)>>>;
+
+pub fn solve_testcase(input_file: &str, collation: Collation) -> std::io::Result<()> {
     let file = File::open(input_file)?;
     let file_hash = input_file.split("_").last().unwrap().split(".").next().unwrap();
     let row_count = input_file.split("_").nth(1).unwrap().parse::<usize>().unwrap();
@@ -22,10 +26,23 @@ pub fn solve_testcase(input_file: &str) -> std::io::Result<()> {
     let output_file: String = format!("testcases/answer_{}_{}.txt", row_count, file_hash);
     let mut file: File = OpenOptions::new().create(true).write(true).truncate(true).open(output_file)?;
 
+    for answer_line in solve(reader.lines(), collation)? {
+        writeln!(file, "{}", answer_line)?;
+    }
+
+    Ok(())
+}
+
+/// Computes the reference answer for testcase lines, one `city=min/mean/max`
+/// string per station in `collation` order.
+pub fn solve(
+    lines: impl Iterator<Item = std::io::Result<String>>,
+    collation: Collation,
+) -> std::io::Result<Vec<String>> {
     // Use integers to store temperatures (multiplied by 10)
     let mut records: HashMap<String, (i64, i64, i64, usize)> = HashMap::new();
 
-    for line in reader.lines() {
+    for line in lines {
         let line = line?;
         let (city, temp_str) = line.split_once(";").unwrap();
 
@@ -49,31 +66,23 @@ pub fn solve_testcase(input_file: &str) -> std::io::Result<()> {
     }
 
     let mut keys: Vec<String> = records.keys().cloned().collect();
-    keys.sort();
+    collation.sort(&mut keys);
 
+    let mut answer_lines = Vec::with_capacity(keys.len());
     for key in keys {
         let value = records.get(&key);
 
         match value {
             Some((min_temp, total_temp, max_temp, count)) => {
                 let avg = (*total_temp as f64 / *count as f64).ceil();
-                // println!("Total Temp for {}: {} with avg of {}", key, total_temp, avg);
                 // Format with one decimal place by dividing by 10
-                writeln!(
-                    file,
+                answer_lines.push(format!(
                     "{}={:.1}/{:.1}/{:.1}",
                     key,
                     *min_temp as f64 / 10.0,
                     avg / 10.0,
                     *max_temp as f64 / 10.0
-                )?;
-                // println!(
-                //     "{}={:.1}/{:.1}/{:.1}",
-                //     key,
-                //     *min_temp as f64 / 10.0,
-                //     avg as f64 / 10.0,
-                //     *max_temp as f64 / 10.0
-                // );
+                ));
             }
             None => {
                 println!("City: {}, No data", key);
@@ -81,11 +90,11 @@ pub fn solve_testcase(input_file: &str) -> std::io::Result<()> {
         }
     }
 
-    Ok(())
+    Ok(answer_lines)
 }
 
 #[allow(dead_code)]
-pub fn solve_optimized(input_file: &str) -> std::io::Result<()> {
+pub fn solve_optimized(input_file: &str, collation: Collation) -> std::io::Result<()> {
     let start = Instant::now();
     let file = File::open(input_file)?;
     let file_hash = input_file.split("_").last().unwrap().split(".").next().unwrap();
@@ -93,7 +102,7 @@ pub fn solve_optimized(input_file: &str) -> std::io::Result<()> {
     println!("Solving test case file: {}", input_file);
     
     let output_file = format!("testcases/answer_{}_{}.txt", row_count, file_hash);
-    let results = Arc::new(Mutex::new(HashMap::<String, (f32, f64, f32, usize)>::new()));
+    let results: SharedResults = Arc::new(Mutex::new(HashMap::new()));
     let mut reader = BufReader::with_capacity(8 * 1024 * 1024, file); 
     
     let chunk_size = std::cmp::min(10_000_000, row_count / 100); 
@@ -146,27 +155,27 @@ pub fn solve_optimized(input_file: &str) -> std::io::Result<()> {
     }
 
     // Write results
-    write_results(&results, &output_file)?;
+    write_results(&results, &output_file, collation)?;
 
     let duration = start.elapsed();
     println!("Processing completed in {:.2?}", duration);
     Ok(())
 }
 
-fn process_chunk(lines: Vec<String>, results: Arc<Mutex<HashMap<String, (f32, f64, f32, usize)>>>) {
+fn process_chunk(lines: Vec<String>, results: SharedResults) {
     let mut local_map = HashMap::new();
 
     for line in lines {
-        if let Some((city, temp_str)) = line.trim().split_once(";") {
-            if let Ok(temp) = temp_str.parse::<f32>() {
-                let entry = local_map
-                    .entry(city.to_string())
-                    .or_insert((temp, temp as f64, temp, 0));
-                entry.0 = entry.0.min(temp);
-                entry.1 += temp as f64;
-                entry.2 = entry.2.max(temp);
-                entry.3 += 1;
-            }
+        if let Some((city, temp_str)) = line.trim().split_once(";")
+            && let Ok(temp) = temp_str.parse::<f32>()
+        {
+            let entry = local_map
+                .entry(city.to_string())
+                .or_insert((temp, temp as f64, temp, 0));
+            entry.0 = entry.0.min(temp);
+            entry.1 += temp as f64;
+            entry.2 = entry.2.max(temp);
+            entry.3 += 1;
         }
     }
 
@@ -183,7 +192,7 @@ fn process_chunk(lines: Vec<String>, results: Arc<Mutex<HashMap<String, (f32, f6
     }
 }
 
-fn write_results(results: &Arc<Mutex<HashMap<String, (f32, f64, f32, usize)>>>, output_file: &str) -> std::io::Result<()> {
+fn write_results(results: &SharedResults, output_file: &str, collation: Collation) -> std::io::Result<()> {
     let global_results = results.lock().unwrap();
     let mut output = OpenOptions::new()
         .create(true)
@@ -192,7 +201,7 @@ fn write_results(results: &Arc<Mutex<HashMap<String, (f32, f64, f32, usize)>>>,
         .open(output_file)?;
 
     let mut keys: Vec<String> = global_results.keys().cloned().collect();
-    keys.sort();
+    collation.sort(&mut keys);
 
     for key in keys {
         if let Some((min_temp, total_temp, max_temp, count)) = global_results.get(&key) {
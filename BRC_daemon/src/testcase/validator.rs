@@ -1,156 +1,1115 @@
-use std::collections::HashMap;
-use std::io::{self, BufRead};
+use std::collections::{HashMap, HashSet};
+use std::collections::hash_map::Entry;
+use std::fmt;
 use std::fs::File;
+use std::io::{self, BufRead, Read};
+use serde::{Deserialize, Serialize};
+use crate::testcase::collation::Collation;
+use crate::testcase::commitment::Commitment;
+use crate::testcase::format::OutputFormat;
+use crate::testcase::hints::{self, Hint};
+use crate::utils::{file_manager, results};
+
+const EPSILON: f64 = 1e-6;
+const MAX_REPORTED_DIFFERENCES: usize = 100;
+const MAX_REPORTED_LINE_LENGTH: usize = 200;
+
+pub const REPORT_FILE_NAME: &str = "validation_report.json";
+
+/// How closely the submission's output has to match the reference.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Strictness {
+    /// Values are compared numerically, blank lines are ignored.
+    #[default]
+    Lenient,
+    /// Values need exactly one decimal place, no blank lines and a trailing newline.
+    Canonical,
+    /// Every line has to be byte-for-byte identical to the reference.
+    ByteExact,
+}
+
+impl fmt::Display for Strictness {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Strictness::Lenient => write!(f, "lenient"),
+            Strictness::Canonical => write!(f, "canonical"),
+            Strictness::ByteExact => write!(f, "byte-exact"),
+        }
+    }
+}
+
+/// Per-level knobs for how a submission's output is checked and scored.
+#[derive(Debug, Clone, Copy)]
+pub struct ValidationOptions {
+    pub strictness: Strictness,
+    pub format: OutputFormat,
+    pub collation: Collation,
+    /// Fraction of stations that must be fully correct for the run to pass.
+    pub pass_threshold: f64,
+    pub limits: OutputLimits,
+}
+
+/// Hard caps on the submission output the validator is willing to read.
+/// Anything bigger fails the run before it is held in memory.
+#[derive(Debug, Clone, Copy)]
+pub struct OutputLimits {
+    pub max_bytes: u64,
+    pub max_lines: usize,
+    pub max_line_length: usize,
+}
+
+fn limit_exceeded(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::FileTooLarge, message)
+}
 
 pub struct ValidationResult {
     pub success: bool,
     pub message: String,
+    pub report: Option<ValidationReport>,
 }
 
-pub fn validate_output(expected_output_lines: &[String], test_output_path: &str) -> io::Result<ValidationResult> {
-    println!("Testing output...");
-    
-    let test_output_file = match File::open(test_output_path) {
-        Ok(f) => f,
-        Err(e) => {
-            return Ok(ValidationResult {
-                success: false,
-                message: format!("Failed to open test output file: {}", e),
-            });
+#[derive(Debug, Clone, Serialize)]
+pub struct StationRecord {
+    pub name: String,
+    pub min: f64,
+    pub mean: f64,
+    pub max: f64,
+}
+
+impl StationRecord {
+    pub fn field(&self, field: Field) -> f64 {
+        match field {
+            Field::Min => self.min,
+            Field::Mean => self.mean,
+            Field::Max => self.max,
         }
+    }
+}
+
+/// Parses one min/mean/max value, enforcing the canonical spelling unless
+/// validation is lenient. NaN and infinities are never valid, since they
+/// would compare as within tolerance of anything.
+pub fn parse_value(field: Field, value: &str, strictness: Strictness) -> Result<f64, ParseErrorKind> {
+    let parsed = value
+        .parse::<f64>()
+        .ok()
+        .filter(|parsed| parsed.is_finite())
+        .ok_or_else(|| ParseErrorKind::InvalidValue {
+            field,
+            value: value.to_string(),
+        })?;
+    if strictness != Strictness::Lenient && !is_canonical_value(value) {
+        return Err(ParseErrorKind::NonCanonicalValue {
+            field,
+            value: value.to_string(),
+        });
+    }
+    Ok(parsed)
+}
+
+/// A canonical value is an optional '-', an integer part without leading
+/// zeros and exactly one decimal digit, e.g. `-0.0`, `0.0` or `12.3`.
+fn is_canonical_value(value: &str) -> bool {
+    let unsigned = value.strip_prefix('-').unwrap_or(value);
+    let Some((integer, fraction)) = unsigned.split_once('.') else {
+        return false;
     };
-    
-    let test_output_reader = io::BufReader::new(test_output_file);
-    let test_output_lines: Vec<String> = test_output_reader
-        .lines()
-        .filter_map(|line| {
-            let line = line.expect("Failed to read line");
-            if line.trim().is_empty() {
-                None
-            } else {
-                Some(line)
+
+    let integer_ok = !integer.is_empty()
+        && integer.bytes().all(|b| b.is_ascii_digit())
+        && (integer == "0" || !integer.starts_with('0'));
+    let fraction_ok = fraction.len() == 1 && fraction.bytes().all(|b| b.is_ascii_digit());
+
+    integer_ok && fraction_ok
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Field {
+    Min,
+    Mean,
+    Max,
+}
+
+impl Field {
+    pub const ALL: [Field; 3] = [Field::Min, Field::Mean, Field::Max];
+}
+
+impl fmt::Display for Field {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Field::Min => write!(f, "min"),
+            Field::Mean => write!(f, "mean"),
+            Field::Max => write!(f, "max"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OutputSource {
+    Expected,
+    Submission,
+}
+
+impl fmt::Display for OutputSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OutputSource::Expected => write!(f, "expected output"),
+            OutputSource::Submission => write!(f, "test output"),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ParseErrorKind {
+    InvalidUtf8,
+    MissingSeparator,
+    MissingBraces,
+    MissingHeader { expected: String },
+    InvalidJson { message: String },
+    EmptyStation,
+    ValueCount { found: usize },
+    InvalidValue { field: Field, value: String },
+    NonCanonicalValue { field: Field, value: String },
+    BlankLine,
+    CarriageReturn,
+    MissingTrailingNewline,
+    ByteMismatch { expected: Option<String> },
+}
+
+#[derive(Debug, Serialize)]
+pub struct ParseError {
+    pub source: OutputSource,
+    pub line_number: usize,
+    pub raw: String,
+    #[serde(flatten)]
+    pub kind: ParseErrorKind,
+}
+
+impl ParseError {
+    fn new(source: OutputSource, line_number: usize, raw: &str, kind: ParseErrorKind) -> Self {
+        let raw = if raw.chars().count() > MAX_REPORTED_LINE_LENGTH {
+            let mut truncated: String = raw.chars().take(MAX_REPORTED_LINE_LENGTH).collect();
+            truncated.push_str("...");
+            truncated
+        } else {
+            raw.to_string()
+        };
+
+        ParseError {
+            source,
+            line_number,
+            raw,
+            kind,
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Malformed line {} in {}: ", self.line_number, self.source)?;
+        match &self.kind {
+            ParseErrorKind::InvalidUtf8 => write!(f, "line is not valid UTF-8")?,
+            ParseErrorKind::MissingSeparator => write!(f, "missing '=' between station and values")?,
+            ParseErrorKind::MissingBraces => write!(f, "stations must be wrapped in '{{' and '}}'")?,
+            ParseErrorKind::MissingHeader { expected } => write!(f, "expected header '{}'", expected)?,
+            ParseErrorKind::InvalidJson { message } => write!(f, "invalid JSON station object: {}", message)?,
+            ParseErrorKind::EmptyStation => write!(f, "station name is empty")?,
+            ParseErrorKind::ValueCount { found } => {
+                write!(f, "expected 3 values (min, mean, max), got {}", found)?
             }
-        })
-        .collect();
-    
-    if test_output_lines.len() != expected_output_lines.len() {
-        return Ok(ValidationResult {
-            success: false,
-            message: format!(
-                "Number of cities mismatch: expected {} cities, got {}",
-                expected_output_lines.len(),
-                test_output_lines.len()
+            ParseErrorKind::InvalidValue { field, value } => {
+                write!(f, "invalid {} value '{}'", field, value)?
+            }
+            ParseErrorKind::NonCanonicalValue { field, value } => write!(
+                f,
+                "{} value '{}' must be written with exactly one decimal place, like 12.3 or -0.0",
+                field, value
+            )?,
+            ParseErrorKind::BlankLine => write!(f, "blank lines are not allowed")?,
+            ParseErrorKind::CarriageReturn => write!(f, "line ends with '\\r', use '\\n' line endings")?,
+            ParseErrorKind::MissingTrailingNewline => write!(f, "output must end with a newline")?,
+            ParseErrorKind::ByteMismatch { expected: Some(expected) } => {
+                write!(f, "line differs from the reference, expected '{}'", expected)?
+            }
+            ParseErrorKind::ByteMismatch { expected: None } => {
+                write!(f, "reference output has no line here")?
+            }
+        }
+        write!(f, " (line: '{}')", self.raw)
+    }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Difference {
+    Missing {
+        station: String,
+    },
+    Extra {
+        station: String,
+    },
+    Duplicate {
+        station: String,
+        first_position: usize,
+        duplicate_position: usize,
+    },
+    OutOfOrder {
+        station: String,
+        expected_position: usize,
+        actual_position: usize,
+    },
+    WrongValue {
+        station: String,
+        field: Field,
+        expected: f64,
+        actual: f64,
+    },
+    /// Some value is wrong, but only a commitment was available to say so.
+    WrongValues {
+        station: String,
+    },
+}
+
+impl Difference {
+    pub fn station(&self) -> &str {
+        match self {
+            Difference::Missing { station }
+            | Difference::Extra { station }
+            | Difference::Duplicate { station, .. }
+            | Difference::OutOfOrder { station, .. }
+            | Difference::WrongValue { station, .. }
+            | Difference::WrongValues { station } => station,
+        }
+    }
+}
+
+impl fmt::Display for Difference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Difference::Missing { station } => write!(f, "Missing city {} in test output", station),
+            Difference::Extra { station } => write!(f, "Unexpected city {} in test output", station),
+            Difference::Duplicate { station, first_position, duplicate_position } => write!(
+                f,
+                "Duplicate city {} in test output: first at position {}, repeated at position {}",
+                station, first_position, duplicate_position
+            ),
+            Difference::OutOfOrder { station, expected_position, actual_position } => write!(
+                f,
+                "City '{}' is out of order: expected at position {}, found at position {}",
+                station, expected_position, actual_position
             ),
+            Difference::WrongValue { station, field, expected, actual } => write!(
+                f,
+                "Value mismatch for city {} ({}): expected {}, got {}",
+                station, field, expected, actual
+            ),
+            Difference::WrongValues { station } => {
+                write!(f, "Values for city {} do not match the committed answer", station)
+            }
+        }
+    }
+}
+
+/// Both sides of a station whose values are wrong, kept whole so a diff can
+/// show every field next to the reference.
+#[derive(Debug, Serialize)]
+pub struct StationMismatch {
+    pub expected: StationRecord,
+    pub actual: StationRecord,
+}
+
+/// Partial credit for a comparison. Station and field scores are fractions
+/// of the scored stations, which are the expected ones plus any extras and
+/// malformed lines, neither of which is ever correct.
+#[derive(Debug, Default, Serialize)]
+pub struct Score {
+    pub correctness: f64,
+    pub correct_stations: usize,
+    pub scored_stations: usize,
+    /// Per-field fractions, unknown when checking against a commitment.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mean: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max: Option<f64>,
+    pub pass_threshold: f64,
+}
+
+impl Score {
+    pub fn passed(&self) -> bool {
+        self.correctness >= self.pass_threshold
+    }
+
+    fn summary(&self) -> String {
+        format!(
+            "Score: {}/{} stations correct ({:.1}%, {:.1}% needed to pass)",
+            self.correct_stations,
+            self.scored_stations,
+            self.correctness * 100.0,
+            self.pass_threshold * 100.0
+        )
+    }
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct ValidationReport {
+    pub strictness: Strictness,
+    pub format: OutputFormat,
+    pub collation: Collation,
+    pub expected_stations: usize,
+    pub actual_stations: usize,
+    pub missing: usize,
+    pub extra: usize,
+    pub duplicate: usize,
+    pub out_of_order: usize,
+    pub wrong_value: usize,
+    pub malformed_lines: usize,
+    pub truncated: bool,
+    pub score: Option<Score>,
+    pub hints: Vec<Hint>,
+    pub parse_errors: Vec<ParseError>,
+    pub differences: Vec<Difference>,
+    pub mismatched_stations: Vec<StationMismatch>,
+}
+
+impl ValidationReport {
+    fn record(&mut self, difference: Difference) {
+        match difference {
+            Difference::Missing { .. } => self.missing += 1,
+            Difference::Extra { .. } => self.extra += 1,
+            Difference::Duplicate { .. } => self.duplicate += 1,
+            Difference::OutOfOrder { .. } => self.out_of_order += 1,
+            Difference::WrongValue { .. } | Difference::WrongValues { .. } => self.wrong_value += 1,
+        }
+
+        if self.differences.len() < MAX_REPORTED_DIFFERENCES {
+            self.differences.push(difference);
+        } else {
+            self.truncated = true;
+        }
+    }
+
+    fn record_mismatch(&mut self, expected: &StationRecord, actual: &StationRecord) {
+        if self.mismatched_stations.len() < MAX_REPORTED_DIFFERENCES {
+            self.mismatched_stations.push(StationMismatch {
+                expected: expected.clone(),
+                actual: actual.clone(),
+            });
+        } else {
+            self.truncated = true;
+        }
+    }
+
+    fn record_parse_error(&mut self, error: ParseError) {
+        self.malformed_lines += 1;
+
+        if self.parse_errors.len() < MAX_REPORTED_DIFFERENCES {
+            self.parse_errors.push(error);
+        } else {
+            self.truncated = true;
+        }
+    }
+
+    pub fn total_differences(&self) -> usize {
+        self.missing + self.extra + self.duplicate + self.out_of_order + self.wrong_value
+    }
+
+    pub fn summary(&self) -> String {
+        let mut summary = self.differences_summary();
+        if let Some(hint) = self.hints.first() {
+            summary.push_str(&format!(". Hint: {}", hint.message));
+        }
+        summary
+    }
+
+    fn differences_summary(&self) -> String {
+        let malformed = self.parse_errors.first().map(|first| {
+            format!(
+                "{} malformed lines found ({} validation of {} output). First: {}",
+                self.malformed_lines, self.strictness, self.format, first
+            )
         });
+        if let Some(malformed) = &malformed
+            && self.total_differences() == 0
+        {
+            return match &self.score {
+                Some(score) => format!("{}. {}", malformed, score.summary()),
+                None => malformed.clone(),
+            };
+        }
+
+        let mut summary = format!(
+            "{} differences found ({} missing, {} unexpected, {} duplicated, {} out of order, {} wrong values)",
+            self.total_differences(),
+            self.missing,
+            self.extra,
+            self.duplicate,
+            self.out_of_order,
+            self.wrong_value
+        );
+        if let Some(score) = &self.score {
+            summary.push_str(&format!(". {}", score.summary()));
+        }
+        if let Some(first) = self.differences.first() {
+            summary.push_str(&format!(". First: {}", first));
+        }
+        if self.out_of_order > 0 {
+            summary.push_str(&format!(". Stations must be sorted by {}", self.collation));
+        }
+        match malformed {
+            Some(malformed) => format!("{}. {}", malformed, summary),
+            None => summary,
+        }
+    }
+}
+
+pub fn write_report(report: &ValidationReport) -> io::Result<()> {
+    results::write_artifact(REPORT_FILE_NAME, &serde_json::to_vec_pretty(report)?)
+}
+
+/// Reads the submission's lines, failing with `FileTooLarge` as soon as the
+/// output goes past one of `limits`.
+fn read_output_lines(
+    reader: impl Read,
+    reference_lines: &[String],
+    limits: OutputLimits,
+    report: &mut ValidationReport,
+) -> io::Result<Vec<(usize, String)>> {
+    let strictness = report.strictness;
+    let mut reader = io::BufReader::new(reader.take(limits.max_bytes.saturating_add(1)));
+    let mut lines = Vec::new();
+    let mut buffer = Vec::new();
+    let mut line_number = 0;
+    let mut total_bytes: u64 = 0;
+    let mut ends_with_newline = true;
+
+    loop {
+        buffer.clear();
+        // One byte past the limit (plus the newline) is enough to tell the line is too long
+        let line_limit = limits.max_line_length as u64 + 2;
+        if (&mut reader).take(line_limit).read_until(b'\n', &mut buffer)? == 0 {
+            break;
+        }
+        line_number += 1;
+
+        total_bytes += buffer.len() as u64;
+        if total_bytes > limits.max_bytes {
+            return Err(limit_exceeded(format!("Output is larger than the limit of {} bytes", limits.max_bytes)));
+        }
+        if line_number > limits.max_lines {
+            return Err(limit_exceeded(format!("Output has more than the limit of {} lines", limits.max_lines)));
+        }
+        let content_length = buffer.len() - usize::from(buffer.last() == Some(&b'\n'));
+        if content_length > limits.max_line_length {
+            return Err(limit_exceeded(format!(
+                "Line {} of the output is longer than the limit of {} bytes",
+                line_number, limits.max_line_length
+            )));
+        }
+
+        ends_with_newline = buffer.last() == Some(&b'\n');
+        if ends_with_newline {
+            buffer.pop();
+        }
+
+        let mut record_error = |raw: &str, kind| {
+            report.record_parse_error(ParseError::new(OutputSource::Submission, line_number, raw, kind))
+        };
+
+        if buffer.last() == Some(&b'\r') {
+            if strictness == Strictness::Lenient {
+                buffer.pop();
+            } else {
+                record_error(&String::from_utf8_lossy(&buffer), ParseErrorKind::CarriageReturn);
+                continue;
+            }
+        }
+
+        let line = match std::str::from_utf8(&buffer) {
+            Ok(line) => line,
+            Err(_) => {
+                record_error(&String::from_utf8_lossy(&buffer), ParseErrorKind::InvalidUtf8);
+                continue;
+            }
+        };
+
+        if strictness == Strictness::ByteExact {
+            let expected = reference_lines.get(line_number - 1);
+            if expected.map(String::as_str) != Some(line) {
+                record_error(line, ParseErrorKind::ByteMismatch { expected: expected.cloned() });
+                continue;
+            }
+        }
+
+        if line.trim().is_empty() {
+            if strictness != Strictness::Lenient {
+                record_error(line, ParseErrorKind::BlankLine);
+            }
+            continue;
+        }
+
+        lines.push((line_number, line.to_string()));
+    }
+
+    if strictness != Strictness::Lenient && !ends_with_newline {
+        let last_line = lines.last().map(|(_, line)| line.as_str()).unwrap_or_default();
+        report.record_parse_error(ParseError::new(
+            OutputSource::Submission,
+            line_number,
+            last_line,
+            ParseErrorKind::MissingTrailingNewline,
+        ));
+    }
+
+    Ok(lines)
+}
+
+fn parse_records<'a>(
+    lines: impl Iterator<Item = (usize, &'a str)>,
+    source: OutputSource,
+    format: OutputFormat,
+    strictness: Strictness,
+    report: &mut ValidationReport,
+) -> Vec<StationRecord> {
+    let mut records = Vec::new();
+    for (i, (line_number, line)) in lines.enumerate() {
+        let entries = match format.split_line(line, i == 0) {
+            Ok(entries) => entries,
+            Err(kind) => {
+                report.record_parse_error(ParseError::new(source, line_number, line, kind));
+                continue;
+            }
+        };
+
+        for entry in entries {
+            match format.parse_entry(entry, strictness) {
+                Ok(record) => records.push(record),
+                Err(kind) => report.record_parse_error(ParseError::new(source, line_number, entry, kind)),
+            }
+        }
+    }
+    records
+}
+
+/// Opens, reads and parses the submission's output. A failure that should be
+/// reported as the verdict, rather than as a daemon error, comes back as `Err`.
+fn read_submission(
+    test_output_path: &str,
+    reference_lines: &[String],
+    options: ValidationOptions,
+    report: &mut ValidationReport,
+) -> io::Result<Result<Vec<StationRecord>, String>> {
+    let limits = options.limits;
+    let test_output_file = match File::open(test_output_path) {
+        Ok(f) => f,
+        Err(e) => return Ok(Err(format!("Failed to open test output file: {}", e))),
+    };
+    if test_output_file.metadata()?.len() > limits.max_bytes {
+        return Ok(Err(format!("Output is larger than the limit of {} bytes", limits.max_bytes)));
+    }
+
+    let test_output_lines = match read_output_lines(test_output_file, reference_lines, limits, report) {
+        Ok(lines) => lines,
+        Err(e) if e.kind() == io::ErrorKind::FileTooLarge => return Ok(Err(e.to_string())),
+        Err(e) => return Ok(Err(format!("Failed to read test output file: {}", e))),
+    };
+
+    Ok(Ok(parse_records(
+        test_output_lines
+            .iter()
+            .map(|(line_number, line)| (*line_number, line.as_str())),
+        OutputSource::Submission,
+        options.format,
+        options.strictness,
+        report,
+    )))
+}
+
+fn new_report(options: ValidationOptions) -> ValidationReport {
+    ValidationReport {
+        strictness: options.strictness,
+        format: options.format,
+        collation: options.collation,
+        ..Default::default()
+    }
+}
+
+fn failure(message: String) -> ValidationResult {
+    ValidationResult {
+        success: false,
+        message,
+        report: None,
+    }
+}
+
+fn scored(score: Score, mut report: ValidationReport) -> ValidationResult {
+    report
+        .parse_errors
+        .sort_by_key(|error| (error.source, error.line_number));
+    // Without partial credit, any difference or malformed line fails the run
+    let flawless = report.total_differences() == 0 && report.malformed_lines == 0;
+    let success = score.passed() && (score.pass_threshold < 1.0 || flawless);
+    let partial_credit = format!(
+        "Passed with partial credit: {}/{} stations correct ({:.1}%, {:.1}% needed)",
+        score.correct_stations,
+        score.scored_stations,
+        score.correctness * 100.0,
+        score.pass_threshold * 100.0
+    );
+    report.score = Some(score);
+
+    let message = if flawless {
+        "All tests passed successfully! Output matches expected format and order.".to_string()
+    } else if success {
+        partial_credit
+    } else {
+        report.summary()
+    };
+
+    ValidationResult {
+        success,
+        message,
+        report: Some(report),
     }
-    
-    let mut test_city_positions: HashMap<String, usize> = HashMap::new();
-    for (i, line) in test_output_lines.iter().enumerate() {
-        let parts: Vec<&str> = line.split('=').collect();
-        if parts.len() != 2 {
-            return Ok(ValidationResult {
-                success: false,
-                message: format!("Malformed line in test output: {}", line),
+}
+
+pub fn validate_output(
+    expected_output_lines: &[String],
+    test_output_path: &str,
+    testcase_path: &str,
+    options: ValidationOptions,
+) -> io::Result<ValidationResult> {
+    println!("Testing output...");
+
+    let mut report = new_report(options);
+
+    // The reference answer is always written one station per line
+    let expected_records = parse_records(
+        expected_output_lines
+            .iter()
+            .enumerate()
+            .map(|(i, line)| (i + 1, line.as_str())),
+        OutputSource::Expected,
+        OutputFormat::Lines,
+        Strictness::Lenient,
+        &mut report,
+    );
+
+    let reference_lines = match options.format {
+        OutputFormat::Lines => expected_output_lines.to_vec(),
+        format => format.render(&expected_records),
+    };
+
+    let test_records = match read_submission(test_output_path, &reference_lines, options, &mut report)? {
+        Ok(records) => records,
+        Err(message) => return Ok(failure(message)),
+    };
+
+    // Malformed lines don't stop the rest from being scored, they only count against it
+    let score = compare_records(&expected_records, &test_records, options.pass_threshold, &mut report);
+    if report.total_differences() > 0 || report.malformed_lines > 0 {
+        let last_input_station = file_manager::read_last_line(testcase_path)
+            .ok()
+            .flatten()
+            .and_then(|line| line.split_once(';').map(|(station, _)| station.to_string()));
+        report.hints = hints::diagnose(
+            &expected_records,
+            &test_records,
+            &report,
+            last_input_station.as_deref(),
+        );
+    }
+
+    Ok(scored(score, report))
+}
+
+/// Validates against a salted commitment instead of the answer itself. Wrong
+/// stations are named, but their reference values stay unknown, so there are
+/// no value hints and byte-exact checking isn't possible.
+pub fn validate_against_commitment(
+    commitment: &Commitment,
+    test_output_path: &str,
+    options: ValidationOptions,
+) -> io::Result<ValidationResult> {
+    if options.strictness == Strictness::ByteExact {
+        return Err(io::Error::other("Byte-exact validation needs the full answer, not a commitment"));
+    }
+    println!("Testing output against the answer commitment...");
+
+    let mut report = new_report(options);
+    let test_records = match read_submission(test_output_path, &[], options, &mut report)? {
+        Ok(records) => records,
+        Err(message) => return Ok(failure(message)),
+    };
+
+    let score = compare_commitment(commitment, &test_records, options.pass_threshold, &mut report);
+    Ok(scored(score, report))
+}
+
+/// Indexes station names, keeping the first occurrence of each. Later
+/// occurrences are returned separately as (first position, duplicate position).
+fn index_names<'a>(names: impl Iterator<Item = &'a str>) -> (HashMap<&'a str, usize>, Vec<(usize, usize)>) {
+    let mut index: HashMap<&str, usize> = HashMap::new();
+    let mut duplicates = Vec::new();
+
+    for (position, name) in names.enumerate() {
+        match index.entry(name) {
+            Entry::Occupied(first) => duplicates.push((*first.get(), position)),
+            Entry::Vacant(slot) => {
+                slot.insert(position);
+            }
+        }
+    }
+
+    (index, duplicates)
+}
+
+/// Marks the entries of `values` that belong to one longest strictly
+/// increasing subsequence, found by patience sorting in O(n log n).
+fn longest_increasing(values: &[usize]) -> Vec<bool> {
+    // tails[k] is where the smallest value ending an increasing run of k + 1 sits
+    let mut tails: Vec<usize> = Vec::new();
+    let mut previous: Vec<Option<usize>> = vec![None; values.len()];
+    for (i, &value) in values.iter().enumerate() {
+        let length = tails.partition_point(|&tail| values[tail] < value);
+        previous[i] = length.checked_sub(1).map(|k| tails[k]);
+        if length == tails.len() {
+            tails.push(i);
+        } else {
+            tails[length] = i;
+        }
+    }
+
+    let mut in_sequence = vec![false; values.len()];
+    let mut next = tails.last().copied();
+    while let Some(i) = next {
+        in_sequence[i] = true;
+        next = previous[i];
+    }
+    in_sequence
+}
+
+/// Which of a station's values match the reference. A commitment can only
+/// tell whether all of them do.
+enum ValueCheck {
+    Fields([bool; 3]),
+    Whole(bool),
+}
+
+fn compare_records(
+    expected: &[StationRecord],
+    actual: &[StationRecord],
+    pass_threshold: f64,
+    report: &mut ValidationReport,
+) -> Score {
+    let expected_names: Vec<&str> = expected.iter().map(|record| record.name.as_str()).collect();
+    compare_stations(&expected_names, actual, pass_threshold, report, |position, actual_record, report| {
+        let expected_record = &expected[position];
+        let mut correct = [true; 3];
+        for (i, field) in Field::ALL.into_iter().enumerate() {
+            let expected_value = expected_record.field(field);
+            let actual_value = actual_record.field(field);
+            if (actual_value - expected_value).abs() > EPSILON {
+                correct[i] = false;
+                report.record(Difference::WrongValue {
+                    station: expected_record.name.clone(),
+                    field,
+                    expected: expected_value,
+                    actual: actual_value,
+                });
+            }
+        }
+
+        if correct.contains(&false) {
+            report.record_mismatch(expected_record, actual_record);
+        }
+        ValueCheck::Fields(correct)
+    })
+}
+
+fn compare_commitment(
+    commitment: &Commitment,
+    actual: &[StationRecord],
+    pass_threshold: f64,
+    report: &mut ValidationReport,
+) -> Score {
+    let expected_names: Vec<&str> = commitment.stations.iter().map(|station| station.station.as_str()).collect();
+    compare_stations(&expected_names, actual, pass_threshold, report, |position, actual_record, report| {
+        let correct = commitment.matches(position, actual_record);
+        if !correct {
+            report.record(Difference::WrongValues {
+                station: actual_record.name.clone(),
             });
         }
-        test_city_positions.insert(parts[0].to_string(), i);
+        ValueCheck::Whole(correct)
+    })
+}
+
+/// Matches stations by exact name and checks their order, leaving the values
+/// to `check_values`, which gets the expected position and the matching record.
+fn compare_stations(
+    expected: &[&str],
+    actual: &[StationRecord],
+    pass_threshold: f64,
+    report: &mut ValidationReport,
+    mut check_values: impl FnMut(usize, &StationRecord, &mut ValidationReport) -> ValueCheck,
+) -> Score {
+    report.expected_stations = expected.len();
+    report.actual_stations = actual.len();
+
+    let (expected_index, _) = index_names(expected.iter().copied());
+    let (actual_index, duplicates) = index_names(actual.iter().map(|record| record.name.as_str()));
+
+    // A repeated station is wrong however its values compare
+    let mut duplicated: HashSet<&str> = HashSet::new();
+    for (first_position, duplicate_position) in duplicates {
+        duplicated.insert(actual[duplicate_position].name.as_str());
+        report.record(Difference::Duplicate {
+            station: actual[duplicate_position].name.clone(),
+            first_position,
+            duplicate_position,
+        });
     }
-    
-    for (expected_pos, expected_line) in expected_output_lines.iter().enumerate() {
-        let expected_parts: Vec<&str> = expected_line.split('=').collect();
-        if expected_parts.len() != 2 {
-            return Ok(ValidationResult {
-                success: false,
-                message: format!("Malformed line in expected output: {}", expected_line),
+
+    // Ordering is checked over the stations both outputs share. The longest
+    // run of them already in the expected order stays put and only the rest
+    // are out of order, so moving a single station flags just that station
+    let shared: Vec<(usize, &StationRecord)> = actual
+        .iter()
+        .enumerate()
+        .filter(|(position, record)| {
+            actual_index.get(record.name.as_str()) == Some(position)
+                && expected_index.contains_key(record.name.as_str())
+        })
+        .collect();
+    let expected_positions: Vec<usize> = shared
+        .iter()
+        .map(|(_, record)| expected_index[record.name.as_str()])
+        .collect();
+
+    let mut out_of_order: HashSet<&str> = HashSet::new();
+    for ((actual_pos, actual_record), in_order) in shared.into_iter().zip(longest_increasing(&expected_positions)) {
+        if !in_order {
+            out_of_order.insert(actual_record.name.as_str());
+            report.record(Difference::OutOfOrder {
+                station: actual_record.name.clone(),
+                expected_position: expected_index[actual_record.name.as_str()],
+                actual_position: actual_pos,
             });
         }
-    
-        let expected_city = expected_parts[0];
-        let expected_values: Vec<f64> = expected_parts[1]
-            .split('/')
-            .map(|v| {
-                v.parse::<f64>().unwrap_or_else(|_| {
-                    panic!("Failed to parse value in expected output: {}", v);
-                })
-            })
-            .collect();
-    
-        // Check if city exists and get its position
-        match test_city_positions.get(expected_city) {
-            Some(&actual_pos) => {
-                // City exists, but check if it's in the correct position
-                if actual_pos != expected_pos {
-                    return Ok(ValidationResult {
-                        success: false,
-                        message: format!(
-                            "City '{}' is out of order: expected at position {}, found at position {}",
-                            expected_city, expected_pos, actual_pos
-                        ),
-                    });
-                }
-    
-                // Now check the values
-                let test_parts: Vec<&str> = test_output_lines[actual_pos].split('=').collect();
-                let test_values: Vec<f64> = test_parts[1]
-                    .split('/')
-                    .map(|v| {
-                        v.parse::<f64>().unwrap_or_else(|_| {
-                            panic!("Failed to parse value in test output: {}", v);
-                        })
-                    })
-                    .collect();
-    
-                if test_values.len() != expected_values.len() {
-                    return Ok(ValidationResult {
-                        success: false,
-                        message: format!(
-                            "Number of values mismatch for city {}: expected {}, got {}",
-                            expected_city,
-                            expected_values.len(),
-                            test_values.len()
-                        ),
-                    });
-                }
-    
-                // Check each value
-                for (i, (test_val, expected_val)) in test_values.iter().zip(expected_values.iter()).enumerate() {
-                    const EPSILON: f64 = 1e-6;
-                    if (test_val - expected_val).abs() > EPSILON {
-                        return Ok(ValidationResult {
-                            success: false,
-                            message: format!(
-                                "Value mismatch for city {} at position {}: expected {}, got {}",
-                                expected_city, i, expected_val, test_val
-                            ),
-                        });
+    }
+
+    // Missing stations and value mismatches, matched by exact name
+    let mut correct_stations = 0;
+    let mut correct_fields = Some([0usize; 3]);
+    for (position, expected_name) in expected.iter().enumerate() {
+        let Some(&actual_pos) = actual_index.get(expected_name) else {
+            report.record(Difference::Missing {
+                station: expected_name.to_string(),
+            });
+            continue;
+        };
+
+        let all_fields_correct = match check_values(position, &actual[actual_pos], report) {
+            ValueCheck::Fields(correct) => {
+                if let Some(counts) = correct_fields.as_mut() {
+                    for (count, correct) in counts.iter_mut().zip(correct) {
+                        *count += usize::from(correct);
                     }
                 }
+                !correct.contains(&false)
             }
-            None => {
-                return Ok(ValidationResult {
-                    success: false,
-                    message: format!("Missing city {} in test output", expected_city),
-                });
+            ValueCheck::Whole(correct) => {
+                correct_fields = None;
+                correct
             }
+        };
+
+        if all_fields_correct && !out_of_order.contains(expected_name) && !duplicated.contains(expected_name) {
+            correct_stations += 1;
         }
     }
-    
-    // Check for any unexpected cities
-    for test_line in test_output_lines.iter() {
-        let test_city = test_line.split('=').next().unwrap();
-        if !expected_output_lines
-            .iter()
-            .any(|line| line.starts_with(test_city))
+
+    // Unexpected stations, ignoring repeats already reported as duplicates
+    for (position, actual_record) in actual.iter().enumerate() {
+        if actual_index.get(actual_record.name.as_str()) == Some(&position)
+            && !expected_index.contains_key(actual_record.name.as_str())
         {
-            return Ok(ValidationResult {
-                success: false,
-                message: format!("Unexpected city {} in test output", test_city),
+            report.record(Difference::Extra {
+                station: actual_record.name.clone(),
             });
         }
     }
-    
-    Ok(ValidationResult {
-        success: true,
-        message: "All tests passed successfully! Output matches expected format and order.".to_string(),
-    })
-}
\ No newline at end of file
+
+    let scored_stations = expected.len() + report.extra + report.malformed_lines;
+    let fraction = |count: usize| {
+        if scored_stations == 0 {
+            1.0
+        } else {
+            count as f64 / scored_stations as f64
+        }
+    };
+
+    Score {
+        correctness: fraction(correct_stations),
+        correct_stations,
+        scored_stations,
+        min: correct_fields.map(|counts| fraction(counts[0])),
+        mean: correct_fields.map(|counts| fraction(counts[1])),
+        max: correct_fields.map(|counts| fraction(counts[2])),
+        pass_threshold,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXPECTED: [&str; 4] = ["A=1.0/2.0/3.0", "B=4.0/5.0/6.0", "C=7.0/8.0/9.0", "D=-1.0/0.0/1.0"];
+
+    fn options(pass_threshold: f64) -> ValidationOptions {
+        ValidationOptions {
+            strictness: Strictness::Lenient,
+            format: OutputFormat::Lines,
+            collation: Collation::ByteOrder,
+            pass_threshold,
+            limits: OutputLimits {
+                max_bytes: 1024 * 1024,
+                max_lines: 1000,
+                max_line_length: 1024,
+            },
+        }
+    }
+
+    /// Validates `output` against `expected`, both one station per line.
+    fn validate(expected: &[&str], output: &[&str], pass_threshold: f64) -> ValidationResult {
+        let path = std::env::temp_dir().join(format!("brc-validator-test-{}.txt", uuid::Uuid::new_v4()));
+        std::fs::write(&path, output.iter().map(|line| format!("{}\n", line)).collect::<String>()).unwrap();
+        let expected: Vec<String> = expected.iter().map(|line| line.to_string()).collect();
+        let result = validate_output(&expected, path.to_str().unwrap(), "no-testcase.txt", options(pass_threshold));
+        std::fs::remove_file(&path).unwrap();
+        result.unwrap()
+    }
+
+    fn score(result: &ValidationResult) -> &Score {
+        result.report.as_ref().and_then(|report| report.score.as_ref()).unwrap()
+    }
+
+    #[test]
+    fn exact_output_passes() {
+        let result = validate(&EXPECTED, &EXPECTED, 1.0);
+        assert!(result.success, "{}", result.message);
+        assert_eq!(result.report.unwrap().total_differences(), 0);
+    }
+
+    #[test]
+    fn repeated_stations_with_wrong_values_fail() {
+        let output = ["A=1.0/2.0/3.0", "A=1.0/2.0/3.0", "B=4.0/5.0/6.0", "B=9.0/9.0/9.0"];
+        let result = validate(&EXPECTED[..2], &output, 1.0);
+        assert!(!result.success, "{}", result.message);
+        assert_eq!(score(&result).correct_stations, 0);
+        assert_eq!(result.report.unwrap().duplicate, 2);
+    }
+
+    #[test]
+    fn repeated_station_is_never_correct() {
+        let output = ["A=1.0/2.0/3.0", "A=1.0/2.0/3.0", "B=4.0/5.0/6.0"];
+        let result = validate(&EXPECTED[..2], &output, 0.5);
+        assert!(result.success, "{}", result.message);
+        assert_eq!(score(&result).correct_stations, 1);
+    }
+
+    #[test]
+    fn partial_credit_passes_at_its_threshold() {
+        let output = ["A=1.0/2.0/3.0", "B=4.0/5.0/6.0", "C=7.0/8.5/9.0", "D=-1.0/0.0/1.0"];
+        let result = validate(&EXPECTED, &output, 0.75);
+        assert!(result.success, "{}", result.message);
+        assert!(result.message.starts_with("Passed with partial credit: 3/4"), "{}", result.message);
+    }
+
+    #[test]
+    fn any_difference_fails_without_partial_credit() {
+        let output = ["A=1.0/2.0/3.0", "B=4.0/5.0/6.0", "C=7.0/8.5/9.0", "D=-1.0/0.0/1.0"];
+        let result = validate(&EXPECTED, &output, 1.0);
+        assert!(!result.success, "{}", result.message);
+        assert_eq!(result.report.unwrap().wrong_value, 1);
+    }
+
+    #[test]
+    fn missing_and_extra_stations_lower_the_score() {
+        let output = ["A=1.0/2.0/3.0", "B=4.0/5.0/6.0", "C=7.0/8.0/9.0", "E=0.0/0.0/0.0"];
+        let result = validate(&EXPECTED, &output, 0.5);
+        let report = result.report.as_ref().unwrap();
+        assert_eq!((report.missing, report.extra), (1, 1));
+        assert_eq!((score(&result).correct_stations, score(&result).scored_stations), (3, 5));
+    }
+
+    #[test]
+    fn malformed_line_counts_against_the_rest() {
+        let output = ["A=1.0/2.0/3.0", "B=4.0/5.0", "C=7.0/8.0/9.0", "D=-1.0/0.0/1.0"];
+        let result = validate(&EXPECTED, &output, 0.5);
+        assert!(result.success, "{}", result.message);
+        let report = result.report.as_ref().unwrap();
+        assert_eq!((report.malformed_lines, report.missing), (1, 1));
+        assert_eq!((score(&result).correct_stations, score(&result).scored_stations), (3, 5));
+
+        let result = validate(&EXPECTED, &output, 1.0);
+        assert!(!result.success, "{}", result.message);
+        assert!(result.message.starts_with("1 malformed lines found"), "{}", result.message);
+    }
+
+    #[test]
+    fn malformed_line_alone_fails_without_partial_credit() {
+        let output = ["A=1.0/2.0/3.0", "B=4.0/5.0/6.0", "C=7.0/8.0/9.0", "D=-1.0/0.0/1.0", "garbage"];
+        let result = validate(&EXPECTED, &output, 1.0);
+        assert!(!result.success, "{}", result.message);
+        assert!(result.message.contains("Score: 4/5 stations correct"), "{}", result.message);
+    }
+
+    #[test]
+    fn moved_station_is_the_only_one_out_of_order() {
+        let output = ["D=-1.0/0.0/1.0", "A=1.0/2.0/3.0", "B=4.0/5.0/6.0", "C=7.0/8.0/9.0"];
+        let result = validate(&EXPECTED, &output, 0.5);
+        let report = result.report.as_ref().unwrap();
+        assert_eq!(report.out_of_order, 1);
+        assert!(matches!(&report.differences[0], Difference::OutOfOrder { station, .. } if station == "D"));
+        assert_eq!(score(&result).correct_stations, 3);
+    }
+
+    #[test]
+    fn canonical_values_have_one_decimal_and_no_leading_zeros() {
+        for value in ["-0.0", "0.0", "12.3", "-99.9"] {
+            assert!(is_canonical_value(value), "{}", value);
+        }
+        for value in ["00.1", "1.25", "1e1", "1", ".5", "+1.0", "-"] {
+            assert!(!is_canonical_value(value), "{}", value);
+        }
+        assert!(matches!(
+            parse_value(Field::Min, "1.25", Strictness::Canonical),
+            Err(ParseErrorKind::NonCanonicalValue { .. })
+        ));
+        assert!(matches!(parse_value(Field::Min, "1.25", Strictness::Lenient), Ok(value) if value == 1.25));
+    }
+
+    #[test]
+    fn non_finite_values_are_invalid() {
+        for value in ["NaN", "nan", "inf", "-infinity"] {
+            assert!(parse_value(Field::Mean, value, Strictness::Lenient).is_err(), "{}", value);
+        }
+        let output = ["A=NaN/nan/NaN", "B=4.0/5.0/6.0", "C=7.0/8.0/9.0", "D=-1.0/0.0/1.0"];
+        let result = validate(&EXPECTED, &output, 1.0);
+        assert!(!result.success, "{}", result.message);
+    }
+
+    #[test]
+    fn missing_station_does_not_disturb_the_order() {
+        let output = ["A=1.0/2.0/3.0", "C=7.0/8.0/9.0", "D=-1.0/0.0/1.0"];
+        let report = validate(&EXPECTED, &output, 0.5).report.unwrap();
+        assert_eq!((report.missing, report.out_of_order), (1, 0));
+    }
+}
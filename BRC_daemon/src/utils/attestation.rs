@@ -0,0 +1,282 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use serde_json::value::RawValue;
+use sha2::{Digest, Sha256};
+use crate::utils::integrity::{from_hex, to_hex, FileFingerprint};
+use crate::utils::results;
+
+/// File holding the secret the attestation is signed with. Without it no
+/// attestation is made. The key never goes through the environment, which
+/// the submission inherits and can read from `/proc` besides.
+pub const KEY_FILE_ENV: &str = "DAEMON_ATTESTATION_KEY_FILE";
+/// Where the key is looked for when `KEY_FILE_ENV` isn't set. The worker
+/// copies it there between creating the container and starting it.
+pub const DEFAULT_KEY_FILE: &str = "/tmp/brc-attestation.key";
+pub const ATTESTATION_FILE_NAME: &str = "attestation.json";
+const VERSION: u32 = 2;
+const MAX_SUBMISSION_FILES: usize = 1000;
+
+static KEY: OnceLock<Option<Vec<u8>>> = OnceLock::new();
+
+/// Everything a leaderboard entry depends on, bound together by the signature.
+/// A run that fails early is attested too, with whatever it got to by then.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Attestation {
+    pub version: u32,
+    pub created_at: DateTime<Utc>,
+    pub level: Option<f32>,
+    pub testcase_sha256: Option<String>,
+    /// SHA-256 of every submission file, by path relative to `src/`.
+    pub submission: BTreeMap<String, String>,
+    pub output_sha256: Option<String>,
+    pub success: bool,
+    pub message: String,
+    /// SHA-256 of each artifact written to the results directory, including
+    /// `status.json` and `bench_parsed.json`.
+    pub artifacts: BTreeMap<String, String>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SignedAttestation<'a> {
+    #[serde(borrow)]
+    payload: &'a RawValue,
+    hmac_sha256: String,
+}
+
+impl Attestation {
+    pub fn new() -> Self {
+        Attestation {
+            version: VERSION,
+            created_at: Utc::now(),
+            level: None,
+            testcase_sha256: None,
+            submission: BTreeMap::new(),
+            output_sha256: None,
+            success: false,
+            message: String::new(),
+            artifacts: BTreeMap::new(),
+        }
+    }
+
+    /// Records the verdict and signs the attestation into the results
+    /// directory. Call it last, so every other artifact is already covered.
+    pub fn finish(mut self, success: bool, message: &str) -> io::Result<()> {
+        let Some(key) = signing_key() else {
+            println!("No attestation key in {} or {}, skipping the result attestation", KEY_FILE_ENV, DEFAULT_KEY_FILE);
+            return Ok(());
+        };
+
+        self.success = success;
+        self.message = message.to_string();
+        self.artifacts = results::artifact_checksums();
+        results::write_artifact(ATTESTATION_FILE_NAME, &self.seal(key)?)
+    }
+
+    /// The attestation file's contents: the attestation and its signature.
+    fn seal(&self, key: &[u8]) -> io::Result<Vec<u8>> {
+        let payload = serde_json::to_string(self)?;
+        let signed = SignedAttestation {
+            payload: &RawValue::from_string(payload.clone())?,
+            hmac_sha256: to_hex(&sign(key, payload.as_bytes())?),
+        };
+        Ok(serde_json::to_vec(&signed)?)
+    }
+}
+
+/// Reads the key from the file named by `KEY_FILE_ENV`, or `DEFAULT_KEY_FILE`
+/// if there is one, dropping a trailing newline. With `consume`, the file is
+/// deleted and the daemon made undumpable, so nothing started later can get
+/// at the key on disk or in the daemon's memory. Must run while the daemon is still single-threaded,
+/// since it also takes the variable out of the environment children inherit.
+pub fn load_key(consume: bool) -> io::Result<()> {
+    let path = match std::env::var_os(KEY_FILE_ENV) {
+        Some(path) => {
+            unsafe { std::env::remove_var(KEY_FILE_ENV) };
+            PathBuf::from(path)
+        }
+        None if Path::new(DEFAULT_KEY_FILE).exists() => PathBuf::from(DEFAULT_KEY_FILE),
+        None => {
+            KEY.get_or_init(|| None);
+            return Ok(());
+        }
+    };
+
+    let mut key = fs::read(&path)
+        .map_err(|e| io::Error::other(format!("Failed to read attestation key file {}: {}", path.display(), e)))?;
+    if consume {
+        fs::remove_file(&path)
+            .map_err(|e| io::Error::other(format!("Failed to remove attestation key file {}: {}", path.display(), e)))?;
+        if unsafe { libc::prctl(libc::PR_SET_DUMPABLE, 0) } == -1 {
+            return Err(io::Error::other(format!(
+                "Failed to hide the attestation key: {}",
+                io::Error::last_os_error()
+            )));
+        }
+    }
+    if key.ends_with(b"\n") {
+        key.pop();
+    }
+    if key.is_empty() {
+        return Err(io::Error::other(format!("Attestation key file {} is empty", path.display())));
+    }
+    KEY.get_or_init(|| Some(key));
+    Ok(())
+}
+
+/// Takes `KEY_FILE_ENV` out of the environment and leaves the file alone,
+/// for commands that sign nothing but may still start the submission. Same
+/// single-threaded requirement as `load_key`.
+pub fn forget_key() {
+    unsafe { std::env::remove_var(KEY_FILE_ENV) };
+    KEY.get_or_init(|| None);
+}
+
+fn signing_key() -> Option<&'static [u8]> {
+    KEY.get().and_then(Option::as_deref)
+}
+
+fn mac(key: &[u8]) -> io::Result<Hmac<Sha256>> {
+    Hmac::<Sha256>::new_from_slice(key).map_err(|e| io::Error::other(format!("Invalid attestation key: {}", e)))
+}
+
+fn sign(key: &[u8], payload: &[u8]) -> io::Result<Vec<u8>> {
+    let mut mac = mac(key)?;
+    mac.update(payload);
+    Ok(mac.finalize().into_bytes().to_vec())
+}
+
+/// Hashes the files of a submission under `dir`, skipping the daemon's own
+/// files in `exclude` (paths relative to `dir`) and Python's bytecode cache.
+pub fn hash_submission(dir: &str, exclude: &[&str]) -> io::Result<BTreeMap<String, String>> {
+    let mut hashes = BTreeMap::new();
+    let mut pending = vec![Path::new(dir).to_path_buf()];
+
+    while let Some(current) = pending.pop() {
+        for entry in fs::read_dir(&current)? {
+            let entry = entry?;
+            let path = entry.path();
+            let file_type = entry.file_type()?;
+            let relative = path
+                .strip_prefix(dir)
+                .map_err(io::Error::other)?
+                .to_string_lossy()
+                .into_owned();
+
+            if exclude.contains(&relative.as_str()) || entry.file_name() == "__pycache__" {
+                continue;
+            }
+            if file_type.is_dir() {
+                pending.push(path);
+            } else if file_type.is_file() {
+                if hashes.len() >= MAX_SUBMISSION_FILES {
+                    return Err(io::Error::other(format!(
+                        "Submission has more than {} files",
+                        MAX_SUBMISSION_FILES
+                    )));
+                }
+                hashes.insert(relative, to_hex(&FileFingerprint::of(&path.to_string_lossy())?.sha256));
+            }
+        }
+    }
+
+    Ok(hashes)
+}
+
+/// `daemon verify [dir]`: checks the attestation in `dir` (the results
+/// directory by default) against the key and the artifacts next to it.
+pub fn verify(dir: Option<&str>) -> io::Result<()> {
+    let dir = dir.map(Path::new).map(Path::to_path_buf).unwrap_or_else(results::results_dir);
+    let key = signing_key().ok_or_else(|| io::Error::other(format!("{} must be set to verify", KEY_FILE_ENV)))?;
+
+    let contents = fs::read_to_string(dir.join(ATTESTATION_FILE_NAME))?;
+    let signed: SignedAttestation = serde_json::from_str(&contents)
+        .map_err(|e| io::Error::other(format!("Invalid attestation file: {}", e)))?;
+
+    let signature = from_hex(&signed.hmac_sha256).ok_or_else(|| io::Error::other("Invalid attestation signature"))?;
+    let mut mac = mac(key)?;
+    mac.update(signed.payload.get().as_bytes());
+    mac.verify_slice(&signature)
+        .map_err(|_| io::Error::other("Attestation signature does not match, it was altered or signed with another key"))?;
+
+    let attestation: Attestation = serde_json::from_str(signed.payload.get())
+        .map_err(|e| io::Error::other(format!("Invalid attestation payload: {}", e)))?;
+
+    for (name, expected) in &attestation.artifacts {
+        let actual = fs::read(dir.join(name))
+            .map(|contents| to_hex(&Sha256::digest(contents)))
+            .map_err(|e| io::Error::other(format!("Attested artifact {} can't be read: {}", name, e)))?;
+        if &actual != expected {
+            return Err(io::Error::other(format!("Artifact {} was changed after the run", name)));
+        }
+    }
+
+    println!(
+        "Attestation valid: level {}, {} at {}, {} submission files, {} artifacts checked",
+        attestation.level.map_or("unknown".to_string(), |level| level.to_string()),
+        if attestation.success { "passed" } else { "failed" },
+        attestation.created_at,
+        attestation.submission.len(),
+        attestation.artifacts.len()
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Seals an attestation covering `status.json` into a fresh directory.
+    fn attested_dir(key: &[u8]) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("brc-attestation-test-{}", uuid::Uuid::new_v4()));
+        fs::create_dir(&dir).unwrap();
+        let status = br#"{"success":true}"#;
+        fs::write(dir.join("status.json"), status).unwrap();
+
+        let mut attestation = Attestation::new();
+        attestation.success = true;
+        attestation.artifacts.insert("status.json".to_string(), to_hex(&Sha256::digest(status)));
+        fs::write(dir.join(ATTESTATION_FILE_NAME), attestation.seal(key).unwrap()).unwrap();
+        dir
+    }
+
+    #[test]
+    fn attestation_verifies_until_an_artifact_or_the_payload_changes() {
+        let key = KEY.get_or_init(|| Some(b"test-key".to_vec())).as_deref().unwrap();
+        let dir = attested_dir(key);
+        let verify_dir = || verify(Some(dir.to_str().unwrap()));
+        assert!(verify_dir().is_ok());
+
+        let status = dir.join("status.json");
+        let mut contents = fs::read(&status).unwrap();
+        contents[2] ^= 1;
+        fs::write(&status, &contents).unwrap();
+        assert!(verify_dir().is_err());
+        contents[2] ^= 1;
+        fs::write(&status, &contents).unwrap();
+        assert!(verify_dir().is_ok());
+
+        let attestation = dir.join(ATTESTATION_FILE_NAME);
+        let signed = fs::read_to_string(&attestation).unwrap();
+        fs::write(&attestation, signed.replace(r#""success":true"#, r#""success":false"#)).unwrap();
+        let result = verify_dir();
+        fs::remove_dir_all(&dir).unwrap();
+        assert!(result.unwrap_err().to_string().contains("signature does not match"));
+    }
+
+    #[test]
+    fn attestation_signed_with_another_key_is_rejected() {
+        let key = KEY.get_or_init(|| Some(b"test-key".to_vec())).as_deref().unwrap();
+        let other_key: Vec<u8> = key.iter().map(|byte| byte ^ 1).collect();
+        let dir = attested_dir(&other_key);
+        let result = verify(Some(dir.to_str().unwrap()));
+        fs::remove_dir_all(&dir).unwrap();
+        assert!(result.is_err());
+    }
+}
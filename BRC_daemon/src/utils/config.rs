@@ -0,0 +1,296 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+use serde::Deserialize;
+use crate::benchmark::limits::ResourceLimits;
+use crate::benchmark::sandbox::Sandbox;
+use crate::benchmark::test_runner::{PyperfOptions, RunOptions, SubmissionOutput};
+use crate::testcase::collation::Collation;
+use crate::testcase::format::OutputFormat;
+use crate::testcase::validator::{OutputLimits, Strictness, ValidationOptions};
+use crate::utils::results;
+
+pub const DEFAULT_CONFIG_PATH: &str = "daemon_config.json";
+/// Captured stdout lives in the daemon's private directory, away from the
+/// files `main.py` can see.
+pub const STDOUT_CAPTURE_FILE_NAME: &str = "submission_stdout.txt";
+
+/// What the daemon checks a submission against.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AnswerSource {
+    /// Solve the testcase and compare against the full answer.
+    #[default]
+    Answer,
+    /// Use a distributed testcase and its salted commitment, so the answer
+    /// never has to be on the machine.
+    Commitment,
+}
+
+/// How a submission hands its results to the daemon.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OutputChannel {
+    /// `main.py` writes `output_file` next to the testcase.
+    #[default]
+    File,
+    /// `main.py` prints its results, which the daemon captures.
+    Stdout,
+}
+
+/// What times the benchmark.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BenchmarkHarness {
+    /// `python -m pyperf command`, whose results the daemon parses.
+    #[default]
+    Pyperf,
+    /// The daemon runs and times `main.py` itself, `benchmark_warmups` plus
+    /// `benchmark_runs` times.
+    Native,
+}
+
+/// Settings that can vary between levels. Every field falls back to its
+/// default when omitted, so a level only has to list what it changes.
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LevelConfig {
+    pub strictness: Strictness,
+    pub output_format: OutputFormat,
+    /// Sort order of the reference answer, which submissions must follow.
+    pub collation: Collation,
+    /// Fraction of stations that must be correct to pass. Practice levels
+    /// can lower this below 1.0 to hand out partial credit.
+    pub pass_threshold: f64,
+    pub answer_source: AnswerSource,
+    pub output_channel: OutputChannel,
+    /// File `main.py` writes its results to, relative to `src/`.
+    pub output_file: String,
+    /// Caps on the output, whether written to `output_file` or captured
+    /// from stdout. Going past any of them fails the run. `max_output_bytes`
+    /// is also the file size limit unless `max_file_size_bytes` is set, so an
+    /// oversized output is stopped while it's being written.
+    pub max_output_bytes: u64,
+    pub max_output_lines: usize,
+    pub max_line_length: usize,
+    /// Cap on everything else `main.py` prints: stderr, plus stdout when the
+    /// output goes to a file.
+    pub max_log_bytes: u64,
+    /// Runs the submission in its own user, mount, network and PID
+    /// namespaces, with the testcase read-only and no network.
+    pub sandbox: bool,
+    /// Host paths the sandbox exposes read-only on top of the system
+    /// directories, e.g. a Python installed under the home directory.
+    pub sandbox_read_only_paths: Vec<String>,
+    /// Caps on what a single run may use, covering everything it starts.
+    /// Memory and processes go through a cgroup v2 leaf when the daemon's
+    /// cgroup delegates those controllers, and rlimits otherwise.
+    pub max_memory_bytes: Option<u64>,
+    pub max_processes: Option<u64>,
+    pub max_file_size_bytes: Option<u64>,
+    pub max_open_files: Option<u64>,
+    /// Wall-clock limit on the whole benchmark. Each run in it gets the same
+    /// timeout as the test run.
+    pub benchmark_budget_seconds: u64,
+    pub benchmark_harness: BenchmarkHarness,
+    /// Runs of the native harness that are thrown away, then the ones that
+    /// are timed. pyperf has its own settings below.
+    pub benchmark_warmups: u32,
+    pub benchmark_runs: u32,
+    /// pyperf's worker processes, and the warmups and values each one runs.
+    /// Fast levels can afford more samples than slow ones.
+    pub pyperf_processes: u32,
+    pub pyperf_warmups: u32,
+    pub pyperf_values: u32,
+    /// Shortest a value may take once calibrated.
+    pub pyperf_min_time_seconds: f64,
+    /// CPU list the workers are pinned to, such as "2-3".
+    pub pyperf_affinity: Option<String>,
+}
+
+impl Default for LevelConfig {
+    fn default() -> Self {
+        LevelConfig {
+            strictness: Strictness::default(),
+            output_format: OutputFormat::default(),
+            collation: Collation::default(),
+            pass_threshold: 1.0,
+            answer_source: AnswerSource::default(),
+            output_channel: OutputChannel::default(),
+            output_file: "output.txt".to_string(),
+            max_output_bytes: 16 * 1024 * 1024,
+            max_output_lines: 100_000,
+            // The braces format puts every station on a single line
+            max_line_length: 2 * 1024 * 1024,
+            max_log_bytes: 1024 * 1024,
+            sandbox: false,
+            sandbox_read_only_paths: Vec::new(),
+            max_memory_bytes: None,
+            max_processes: None,
+            max_file_size_bytes: None,
+            max_open_files: None,
+            benchmark_budget_seconds: 600,
+            benchmark_harness: BenchmarkHarness::default(),
+            benchmark_warmups: 1,
+            benchmark_runs: 10,
+            pyperf_processes: 1,
+            pyperf_warmups: 1,
+            pyperf_values: 3,
+            pyperf_min_time_seconds: 0.1,
+            pyperf_affinity: None,
+        }
+    }
+}
+
+impl LevelConfig {
+    pub fn validation_options(&self) -> ValidationOptions {
+        ValidationOptions {
+            strictness: self.strictness,
+            format: self.output_format,
+            collation: self.collation,
+            pass_threshold: self.pass_threshold,
+            limits: OutputLimits {
+                max_bytes: self.max_output_bytes,
+                max_lines: self.max_output_lines,
+                max_line_length: self.max_line_length,
+            },
+        }
+    }
+
+    pub fn pyperf_options(&self) -> PyperfOptions {
+        PyperfOptions {
+            processes: self.pyperf_processes,
+            warmups: self.pyperf_warmups,
+            values: self.pyperf_values,
+            min_time_seconds: self.pyperf_min_time_seconds,
+            affinity: self.pyperf_affinity.clone(),
+        }
+    }
+
+    pub fn submission_output(&self) -> SubmissionOutput {
+        match self.output_channel {
+            OutputChannel::File => SubmissionOutput::File {
+                path: format!("src/{}", self.output_file),
+            },
+            OutputChannel::Stdout => SubmissionOutput::Stdout {
+                path: results::private_path(STDOUT_CAPTURE_FILE_NAME).to_string_lossy().into_owned(),
+                max_bytes: self.max_output_bytes,
+            },
+        }
+    }
+
+    pub fn run_options(&self, timeout_seconds: u64) -> io::Result<RunOptions> {
+        let sandbox = if self.sandbox {
+            let workdir = fs::canonicalize("src")?;
+            Some(Sandbox {
+                read_only_files: vec![workdir.join("testcase.txt")],
+                read_only_paths: self.sandbox_read_only_paths.iter().map(PathBuf::from).collect(),
+                workdir,
+            })
+        } else {
+            None
+        };
+
+        Ok(RunOptions {
+            timeout_seconds,
+            benchmark_budget_seconds: self.benchmark_budget_seconds,
+            output: self.submission_output(),
+            max_log_bytes: self.max_log_bytes,
+            sandbox,
+            limits: ResourceLimits {
+                max_memory_bytes: self.max_memory_bytes,
+                max_processes: self.max_processes,
+                max_file_size_bytes: Some(self.max_file_size_bytes.unwrap_or(self.max_output_bytes)),
+                max_open_files: self.max_open_files,
+            },
+        })
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct ConfigFile {
+    levels: HashMap<String, LevelConfig>,
+}
+
+/// Loads the settings for `level` from the file named by `DAEMON_CONFIG`
+/// (or `daemon_config.json`). A missing file or level means defaults.
+pub fn load_level_config(level: f32) -> io::Result<LevelConfig> {
+    let config_path = std::env::var("DAEMON_CONFIG").unwrap_or_else(|_| DEFAULT_CONFIG_PATH.to_string());
+    if !Path::new(&config_path).exists() {
+        return Ok(LevelConfig::default());
+    }
+
+    let contents = fs::read_to_string(&config_path)?;
+    let mut config: ConfigFile = serde_json::from_str(&contents)
+        .map_err(|e| io::Error::other(format!("Invalid config file {}: {}", config_path, e)))?;
+
+    let level_config = config.levels.remove(&level.to_string()).unwrap_or_default();
+    if !(0.0..=1.0).contains(&level_config.pass_threshold) {
+        return Err(io::Error::other(format!(
+            "pass_threshold for level {} must be between 0 and 1, got {}",
+            level, level_config.pass_threshold
+        )));
+    }
+
+    if level_config.answer_source == AnswerSource::Commitment && level_config.strictness == Strictness::ByteExact {
+        return Err(io::Error::other(format!(
+            "Level {} can't combine byte_exact strictness with answer commitments",
+            level
+        )));
+    }
+
+    let output_file = Path::new(&level_config.output_file);
+    let inside_src = output_file
+        .components()
+        .all(|component| matches!(component, Component::Normal(_)));
+    if !inside_src || level_config.output_file.is_empty() || output_file == Path::new("testcase.txt") {
+        return Err(io::Error::other(format!(
+            "output_file for level {} must be a relative path inside src/ other than testcase.txt, got '{}'",
+            level, level_config.output_file
+        )));
+    }
+
+    if let Some(path) = level_config.sandbox_read_only_paths.iter().find(|path| !Path::new(path).is_absolute()) {
+        return Err(io::Error::other(format!(
+            "sandbox_read_only_paths for level {} must be absolute, got '{}'",
+            level, path
+        )));
+    }
+
+    // A standard deviation needs at least two values
+    if level_config.benchmark_harness == BenchmarkHarness::Native && level_config.benchmark_runs < 2 {
+        return Err(io::Error::other(format!(
+            "benchmark_runs for level {} must be at least 2, got {}",
+            level, level_config.benchmark_runs
+        )));
+    }
+
+    if level_config.benchmark_harness == BenchmarkHarness::Pyperf
+        && (level_config.pyperf_processes as u64 * level_config.pyperf_values as u64) < 2
+    {
+        return Err(io::Error::other(format!(
+            "pyperf_processes times pyperf_values for level {} must be at least 2, got {} and {}",
+            level, level_config.pyperf_processes, level_config.pyperf_values
+        )));
+    }
+
+    if level_config.pyperf_min_time_seconds <= 0.0 {
+        return Err(io::Error::other(format!(
+            "pyperf_min_time_seconds for level {} must be positive, got {}",
+            level, level_config.pyperf_min_time_seconds
+        )));
+    }
+
+    if let Some(affinity) = &level_config.pyperf_affinity
+        && (affinity.is_empty() || !affinity.chars().all(|c| c.is_ascii_digit() || c == '-' || c == ','))
+    {
+        return Err(io::Error::other(format!(
+            "pyperf_affinity for level {} must be a CPU list such as '0-3' or '1,3', got '{}'",
+            level, affinity
+        )));
+    }
+
+    Ok(level_config)
+}
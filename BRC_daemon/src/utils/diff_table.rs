@@ -0,0 +1,143 @@
+use std::env;
+use std::io::{self, IsTerminal};
+use crate::testcase::validator::{Difference, Field, StationRecord, ValidationReport};
+
+const MAX_TABLE_ROWS: usize = 20;
+const MAX_LISTED_STATIONS: usize = 5;
+const MAX_NAME_WIDTH: usize = 32;
+const VALUE_WIDTH: usize = 6;
+const EPSILON: f64 = 1e-6;
+
+const RED: &str = "\x1b[31m";
+const GREEN: &str = "\x1b[32m";
+const BOLD: &str = "\x1b[1m";
+const RESET: &str = "\x1b[0m";
+
+/// Whether failures should be printed for a person rather than the worker.
+/// Local runs in the boilerplate happen in a terminal; the worker's container
+/// never has one.
+pub fn is_client_mode() -> bool {
+    io::stdout().is_terminal()
+}
+
+/// Prints a side-by-side table of the stations with wrong values, followed by
+/// the counts of every other kind of difference.
+pub fn print(report: &ValidationReport) {
+    let color = env::var_os("NO_COLOR").is_none();
+    let paint = |code: &str, text: &str| {
+        if color {
+            format!("{}{}{}", code, text, RESET)
+        } else {
+            text.to_string()
+        }
+    };
+
+    if report.malformed_lines > 0 {
+        println!("{}", paint(BOLD, &format!("{} malformed lines", report.malformed_lines)));
+        for error in report.parse_errors.iter().take(MAX_LISTED_STATIONS) {
+            println!("  {}", error);
+        }
+    }
+
+    if !report.mismatched_stations.is_empty() {
+        let name_width = report
+            .mismatched_stations
+            .iter()
+            .take(MAX_TABLE_ROWS)
+            .map(|mismatch| mismatch.expected.name.chars().count())
+            .max()
+            .unwrap_or(0)
+            .clamp("Station".len(), MAX_NAME_WIDTH);
+        let side_width = VALUE_WIDTH * 3 + 2;
+
+        println!(
+            "{}",
+            paint(
+                BOLD,
+                &format!(
+                    "{:<name_width$}  {:<side_width$}  {:<side_width$}",
+                    "Station", "Expected min/mean/max", "Actual min/mean/max"
+                )
+            )
+        );
+        for mismatch in report.mismatched_stations.iter().take(MAX_TABLE_ROWS) {
+            let wrong: Vec<bool> = Field::ALL
+                .iter()
+                .map(|&field| (mismatch.actual.field(field) - mismatch.expected.field(field)).abs() > EPSILON)
+                .collect();
+            let side = |record: &StationRecord, code: &str| {
+                Field::ALL
+                    .iter()
+                    .zip(&wrong)
+                    .map(|(&field, &wrong)| {
+                        let value = format!("{:>VALUE_WIDTH$.1}", record.field(field));
+                        if wrong { paint(code, &value) } else { value }
+                    })
+                    .collect::<Vec<_>>()
+                    .join("/")
+            };
+            println!(
+                "{}  {}  {}",
+                pad(&mismatch.expected.name, name_width),
+                side(&mismatch.expected, GREEN),
+                side(&mismatch.actual, RED)
+            );
+        }
+
+        let hidden = report.mismatched_stations.len().saturating_sub(MAX_TABLE_ROWS);
+        if hidden > 0 || report.truncated {
+            println!("... and more stations with wrong values, see the validation report");
+        }
+    }
+
+    let listed = |wanted: fn(&Difference) -> Option<&str>| {
+        let names: Vec<&str> = report.differences.iter().filter_map(wanted).take(MAX_LISTED_STATIONS).collect();
+        names.join(", ")
+    };
+    let counts = [
+        // Against a commitment there is no table, so name the stations instead
+        ("Wrong values", report.wrong_value, listed(|d| match d {
+            Difference::WrongValues { station } => Some(station),
+            _ => None,
+        })),
+        ("Missing stations", report.missing, listed(|d| match d {
+            Difference::Missing { station } => Some(station),
+            _ => None,
+        })),
+        ("Extra stations", report.extra, listed(|d| match d {
+            Difference::Extra { station } => Some(station),
+            _ => None,
+        })),
+        ("Duplicated stations", report.duplicate, listed(|d| match d {
+            Difference::Duplicate { station, .. } => Some(station),
+            _ => None,
+        })),
+        ("Out of order stations", report.out_of_order, listed(|d| match d {
+            Difference::OutOfOrder { station, .. } => Some(station),
+            _ => None,
+        })),
+    ];
+
+    println!();
+    for (label, count, examples) in counts {
+        if count == 0 {
+            println!("{}: 0", label);
+        } else if examples.is_empty() {
+            println!("{}: {}", label, paint(RED, &count.to_string()));
+        } else {
+            println!("{}: {} (e.g. {})", label, paint(RED, &count.to_string()), examples);
+        }
+    }
+}
+
+/// Pads or cuts a station name to exactly `width` characters.
+fn pad(name: &str, width: usize) -> String {
+    let length = name.chars().count();
+    if length > width {
+        let mut cut: String = name.chars().take(width.saturating_sub(1)).collect();
+        cut.push('…');
+        cut
+    } else {
+        format!("{}{}", name, " ".repeat(width - length))
+    }
+}
@@ -1,8 +1,9 @@
 use std::path::Path;
-use std::io::{self, BufRead};
+use std::io::{self, BufRead, Read, Seek, SeekFrom};
 use std::fs;
 use crate::testcase::solver;
 use crate::testcase::generator;
+use crate::testcase::collation::Collation;
 
 pub const TESTCASE_PATH: &str = "testcases";
 
@@ -65,15 +66,14 @@ pub fn copy_testcase_to_src_dir(num_rows: usize, testcase_id: &str) -> io::Resul
             })
         },
         Err(e) => {
-            Err(io::Error::new(
-                io::ErrorKind::Other,
+            Err(io::Error::other(
                 format!("Failed to copy testcase file: {}", e)
             ))
         }
     }
 }
 
-pub async fn find_or_create_testcase(num_rows: usize) -> io::Result<String> {
+pub async fn find_or_create_testcase(num_rows: usize, collation: Collation) -> io::Result<String> {
     let testcase_path = Path::new(TESTCASE_PATH);
     let testcase_pattern = format!(
         "./{}/testcase_{}_*.txt",
@@ -84,8 +84,7 @@ pub async fn find_or_create_testcase(num_rows: usize) -> io::Result<String> {
     // Check if testcase already exists
     if let Ok(file) = glob::glob(&testcase_pattern) {
         let files: Vec<_> = file.collect::<Result<Vec<_>, _>>()
-            .map_err(|e| io::Error::new(io::ErrorKind::Other, 
-                format!("Failed to collect testcase files: {}", e)))?;
+            .map_err(|e| io::Error::other(format!("Failed to collect testcase files: {}", e)))?;
                 
         if !files.is_empty() {
             let testcase_file = files[0].to_str().unwrap();
@@ -104,7 +103,7 @@ pub async fn find_or_create_testcase(num_rows: usize) -> io::Result<String> {
                 .to_string();
                 
             // Solve the testcase
-            solver::solve_testcase(testcase_file)?;
+            solver::solve_testcase(testcase_file, collation)?;
             return Ok(testcase_id);
         }
     }
@@ -127,19 +126,75 @@ pub async fn find_or_create_testcase(num_rows: usize) -> io::Result<String> {
         testcase_file_path.to_str().unwrap()
     );
     
-    solver::solve_testcase(testcase_file_path.to_str().unwrap())?;
+    solver::solve_testcase(testcase_file_path.to_str().unwrap(), collation)?;
     
     Ok(testcase_id)
 }
 
+pub fn answer_path(num_rows: usize, testcase_id: &str) -> String {
+    format!("{}/answer_{}_{}.txt", TESTCASE_PATH, num_rows, testcase_id)
+}
+
+pub fn commitment_path(num_rows: usize, testcase_id: &str) -> String {
+    format!("{}/commitment_{}_{}.json", TESTCASE_PATH, num_rows, testcase_id)
+}
+
+/// Finds a distributed testcase that comes with an answer commitment. Unlike
+/// `find_or_create_testcase` this never generates or solves anything.
+pub fn find_committed_testcase(num_rows: usize) -> io::Result<String> {
+    let commitment_pattern = format!("./{}/commitment_{}_*.json", TESTCASE_PATH, num_rows);
+    let files = glob::glob(&commitment_pattern)
+        .map_err(|e| io::Error::other(format!("Invalid commitment pattern: {}", e)))?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| io::Error::other(format!("Failed to collect commitment files: {}", e)))?;
+
+    for file in files {
+        let Some(testcase_id) = file
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .and_then(|stem| stem.rsplit('_').next())
+        else {
+            continue;
+        };
+        let testcase_file = format!("{}/testcase_{}_{}.txt", TESTCASE_PATH, num_rows, testcase_id);
+        if Path::new(&testcase_file).exists() {
+            println!("Using distributed testcase {} with its answer commitment", testcase_file);
+            return Ok(testcase_id.to_string());
+        }
+    }
+
+    Err(io::Error::new(
+        io::ErrorKind::NotFound,
+        format!("No testcase with an answer commitment for {} rows in {}", num_rows, TESTCASE_PATH),
+    ))
+}
+
 pub fn read_lines_from_file(file_path: &str) -> io::Result<Vec<String>> {
     let file = fs::File::open(file_path)?;
     let reader = io::BufReader::new(file);
     reader.lines()
-        .map(|line| line.map_err(|e| io::Error::new(io::ErrorKind::Other, e)))
+        .map(|line| line.map_err(io::Error::other))
         .collect()
 }
 
+/// Reads the last non-empty line of a file without scanning the whole thing.
+pub fn read_last_line(file_path: &str) -> io::Result<Option<String>> {
+    const TAIL_SIZE: u64 = 4096;
+
+    let mut file = fs::File::open(file_path)?;
+    let length = file.metadata()?.len();
+    file.seek(SeekFrom::Start(length.saturating_sub(TAIL_SIZE)))?;
+
+    let mut tail = Vec::new();
+    file.read_to_end(&mut tail)?;
+
+    Ok(String::from_utf8_lossy(&tail)
+        .lines()
+        .rev()
+        .find(|line| !line.trim().is_empty())
+        .map(str::to_string))
+}
+
 pub fn ensure_output_dir() -> io::Result<()> {
     fs::create_dir_all("./output")
 }
\ No newline at end of file
@@ -0,0 +1,90 @@
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::os::unix::fs::{MetadataExt, PermissionsExt};
+use sha2::{Digest, Sha256};
+
+/// Size and SHA-256 of a file, taken before a submission runs so any change
+/// it makes to the file can be detected afterwards.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileFingerprint {
+    pub size: u64,
+    pub sha256: [u8; 32],
+    /// Inode and status change time, which any write, rename or chmod updates
+    /// and which can't be set back, for checks too frequent to hash the file.
+    inode: u64,
+    ctime: (i64, i64),
+}
+
+impl FileFingerprint {
+    pub fn of(path: &str) -> io::Result<Self> {
+        let mut file = File::open(path)?;
+        let metadata = file.metadata()?;
+        let mut hasher = Sha256::new();
+        let mut buffer = vec![0u8; 1024 * 1024];
+        let mut size = 0;
+
+        loop {
+            let read = file.read(&mut buffer)?;
+            if read == 0 {
+                break;
+            }
+            hasher.update(&buffer[..read]);
+            size += read as u64;
+        }
+
+        Ok(FileFingerprint {
+            size,
+            sha256: hasher.finalize().into(),
+            inode: metadata.ino(),
+            ctime: (metadata.ctime(), metadata.ctime_nsec()),
+        })
+    }
+
+    /// Like `changes`, from the file's metadata alone, so it's cheap enough
+    /// to call while a benchmark runs. Never misses a change, but can report
+    /// a file that was rewritten with the same contents.
+    pub fn touched(&self, path: &str) -> Option<String> {
+        match fs::metadata(path) {
+            Err(e) => Some(format!("{} can no longer be read: {}", path, e)),
+            Ok(current) if current.ino() != self.inode || (current.ctime(), current.ctime_nsec()) != self.ctime => {
+                Some(format!("{} was written to or replaced", path))
+            }
+            Ok(_) => None,
+        }
+    }
+
+    /// Describes how the file at `path` differs from this fingerprint, if it does.
+    pub fn changes(&self, path: &str) -> Option<String> {
+        match FileFingerprint::of(path) {
+            Err(e) => Some(format!("{} can no longer be read: {}", path, e)),
+            Ok(current) if current.size != self.size => Some(format!(
+                "{} changed size from {} to {} bytes",
+                path, self.size, current.size
+            )),
+            Ok(current) if current.sha256 != self.sha256 => Some(format!("{} changed contents", path)),
+            Ok(_) => None,
+        }
+    }
+}
+
+/// Makes the file at `path` read-only and fingerprints it. Read-only only
+/// stops an accidental write, since the submission owns the directory, so
+/// the fingerprint is what actually catches tampering.
+pub fn protect(path: &str) -> io::Result<FileFingerprint> {
+    fs::set_permissions(path, fs::Permissions::from_mode(0o444))?;
+    FileFingerprint::of(path)
+}
+
+pub fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+pub fn from_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
@@ -1,2 +1,7 @@
+pub mod attestation;
+pub mod config;
+pub mod diff_table;
 pub mod file_manager;
+pub mod integrity;
+pub mod results;
 pub mod status;
\ No newline at end of file
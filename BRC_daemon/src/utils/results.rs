@@ -0,0 +1,81 @@
+use std::collections::BTreeMap;
+use std::fs::{self, DirBuilder};
+use std::io;
+use std::os::unix::fs::DirBuilderExt;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+use sha2::{Digest, Sha256};
+use crate::utils::integrity::to_hex;
+
+/// Overrides where the final artifacts go. Wherever they are, only the
+/// signed attestation tells the worker they weren't forged.
+pub const RESULTS_DIR_ENV: &str = "DAEMON_RESULTS_DIR";
+
+static PRIVATE_DIR: OnceLock<PathBuf> = OnceLock::new();
+static MANIFEST: Mutex<BTreeMap<String, String>> = Mutex::new(BTreeMap::new());
+
+/// Directory the worker collects artifacts from, `output/` unless overridden.
+pub fn results_dir() -> PathBuf {
+    if let Ok(dir) = std::env::var(RESULTS_DIR_ENV) {
+        return PathBuf::from(dir);
+    }
+    let in_src = std::env::current_dir().is_ok_and(|dir| dir.ends_with("src"));
+    if Path::new("src").exists() && !in_src {
+        PathBuf::from("output")
+    } else {
+        PathBuf::from("../output")
+    }
+}
+
+/// Path for an intermediate file in a directory only the daemon knows about,
+/// created once per run outside the project tree with owner-only access.
+pub fn private_path(name: &str) -> PathBuf {
+    PRIVATE_DIR
+        .get_or_init(|| {
+            let dir = std::env::temp_dir().join(format!("brc-daemon-{}", uuid::Uuid::new_v4()));
+            if let Err(e) = DirBuilder::new().mode(0o700).create(&dir) {
+                eprintln!("Failed to create private directory {}: {}", dir.display(), e);
+            }
+            dir
+        })
+        .join(name)
+}
+
+/// Removes the private directory along with everything in it, the captured
+/// output included. Call it once the run is over.
+pub fn remove_private_dir() {
+    if let Some(dir) = PRIVATE_DIR.get()
+        && let Err(e) = fs::remove_dir_all(dir)
+        && e.kind() != io::ErrorKind::NotFound
+    {
+        eprintln!("Failed to remove private directory {}: {}", dir.display(), e);
+    }
+}
+
+/// Writes a final artifact to the results directory in one rename, so it is
+/// never seen half-written, and records its SHA-256 for the attestation,
+/// which is what lets the worker trust it.
+pub fn write_artifact(name: &str, contents: &[u8]) -> io::Result<()> {
+    let dir = results_dir();
+    fs::create_dir_all(&dir)?;
+
+    write_atomically(&dir, name, contents)?;
+
+    MANIFEST
+        .lock()
+        .map_err(|_| io::Error::other("Artifact manifest lock poisoned"))?
+        .insert(name.to_string(), to_hex(&Sha256::digest(contents)));
+    Ok(())
+}
+
+/// SHA-256 of every artifact this run has written so far, by file name.
+pub fn artifact_checksums() -> BTreeMap<String, String> {
+    MANIFEST.lock().map(|manifest| manifest.clone()).unwrap_or_default()
+}
+
+fn write_atomically(dir: &Path, name: &str, contents: &[u8]) -> io::Result<()> {
+    let temporary_path = dir.join(format!(".{}.tmp", name));
+    fs::write(&temporary_path, contents)?;
+    fs::rename(&temporary_path, dir.join(name))
+        .map_err(|e| io::Error::other(format!("Failed to write {}: {}", name, e)))
+}
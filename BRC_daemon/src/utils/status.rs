@@ -1,35 +1,47 @@
-use std::fs::OpenOptions;
 use std::io;
-use std::path::Path;
+use std::sync::OnceLock;
+use std::time::Duration;
 use serde_json;
+use crate::testcase::hints::Hint;
+use crate::utils::results;
 
-pub async fn write_status(success: bool, message: &str) -> io::Result<()> {
-    let status_file_path = if Path::new("src").exists() && !std::env::current_dir().unwrap().ends_with("src") {
-        "./output/status.json"
-    } else {
-        "../output/status.json"
-    };
-    
-    if let Some(parent_dir) = Path::new(status_file_path).parent() {
-        if !parent_dir.exists() {
-            std::fs::create_dir_all(parent_dir)?;
-        }
-    }
-    
-    let status_file = OpenOptions::new()
-        .write(true)
-        .create(true)
-        .truncate(true)
-        .open(status_file_path)
-        .map_err(|e| io::Error::new(io::ErrorKind::Other, 
-            format!("Failed to open status file: {}", e)))?;
+const STATUS_FILE_NAME: &str = "status.json";
+
+/// Timing of the unbenchmarked run, added to every status written after it.
+static TEST_RUN_TIMING: OnceLock<serde_json::Value> = OnceLock::new();
 
-    let status_writer = io::BufWriter::new(status_file);
-    let json_status = serde_json::json!({
+/// Records how long the unbenchmarked run took, in wall and CPU time.
+pub fn record_test_run(wall_time: Option<Duration>, cpu_time: Option<Duration>) {
+    let nanos = |time: Option<Duration>| time.map(|time| time.as_nanos() as u64);
+    TEST_RUN_TIMING.get_or_init(|| {
+        serde_json::json!({
+            "wall_time_ns": nanos(wall_time),
+            "cpu_time_ns": nanos(cpu_time)
+        })
+    });
+}
+
+pub async fn write_status(success: bool, message: &str) -> io::Result<()> {
+    write_status_json(serde_json::json!({
         "success": success,
         "message": message
-    });
-    
-    serde_json::to_writer(status_writer, &json_status)?;
-    Ok(())
-}
\ No newline at end of file
+    }))
+}
+
+/// Like `write_status`, with diagnostic hints attached for the contestant.
+pub async fn write_status_with_hints(success: bool, message: &str, hints: &[Hint]) -> io::Result<()> {
+    write_status_json(serde_json::json!({
+        "success": success,
+        "message": message,
+        "hints": hints
+    }))
+}
+
+fn write_status_json(mut json_status: serde_json::Value) -> io::Result<()> {
+    if let Some(timing) = TEST_RUN_TIMING.get() {
+        json_status["test_run"] = timing.clone();
+    }
+    let contents = serde_json::to_vec(&json_status)?;
+    results::write_artifact(STATUS_FILE_NAME, &contents)
+        .map_err(|e| io::Error::other(format!("Failed to write status file: {}", e)))
+}
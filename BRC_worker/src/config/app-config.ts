@@ -8,6 +8,8 @@ export const WORKER_NAME = process.env.WORKER_NAME;
 export const TEST_LEVEL = process.env.TEST_LEVEL;
 export const UPGRADE_LEVEL = process.env.UPGRADE_LEVEL;
 export const DATABASE_POOLER_URL = process.env.DATABASE_POOLER_URL;
+// Same secret the daemon signs its attestation with
+export const ATTESTATION_KEY = process.env.ATTESTATION_KEY;
 export const BASE_DIR = dirname(dirname(fileURLToPath(import.meta.url)));
 
 export function createGitHubApp() {
@@ -1,5 +1,7 @@
+import { createHash, createHmac, timingSafeEqual } from "crypto";
 import * as fs from "fs/promises";
 import * as path from "path";
+import { ATTESTATION_KEY } from "../config/app-config.js";
 import type { BenchmarkRawResult, BenchmarkStats } from "../models/benchmark-result.js";
 
 export interface BenchmarkResult {
@@ -11,23 +13,62 @@ export interface BenchmarkResult {
   };
 }
 
+interface Attestation {
+  success: boolean;
+  message: string;
+  artifacts: Record<string, string>;
+}
+
 export class BenchmarkService {
+  // The submission can write to the output directory, so only the daemon's
+  // HMAC over attestation.json says which artifacts are genuine
+  private async readAttestation(outputPath: string): Promise<Attestation> {
+    if (!ATTESTATION_KEY) {
+      throw new Error("ATTESTATION_KEY is not set, results can't be verified");
+    }
+    const content = await fs.readFile(path.join(outputPath, "attestation.json"), "utf-8");
+    // The daemon writes {"payload":<payload>,"hmac_sha256":"<hex>"} and signs
+    // the payload exactly as written
+    const signed = content.match(/^\{"payload":(.*),"hmac_sha256":"([0-9a-f]{64})"\}$/s);
+    if (!signed) {
+      throw new Error("attestation.json is malformed");
+    }
+    const expected = createHmac("sha256", ATTESTATION_KEY).update(signed[1]).digest();
+    if (!timingSafeEqual(expected, Buffer.from(signed[2], "hex"))) {
+      throw new Error("attestation.json is not signed by the daemon");
+    }
+    return JSON.parse(signed[1]);
+  }
+
+  // Only trust an artifact whose SHA-256 the attestation vouches for
+  private async readVerified(
+    outputPath: string,
+    fileName: string,
+    attestation: Attestation
+  ): Promise<string> {
+    const content = await fs.readFile(path.join(outputPath, fileName));
+    const digest = createHash("sha256").update(content).digest("hex");
+
+    if (attestation.artifacts[fileName] !== digest) {
+      throw new Error(`${fileName} does not match the daemon's attestation`);
+    }
+    return content.toString("utf-8");
+  }
+
   async extractResults(outputPath: string): Promise<BenchmarkResult> {
-    const statusPath = path.join(outputPath, "status.json");
     const benchPath = path.join(outputPath, "bench.json");
-    const benchParsedPath = path.join(outputPath, "bench_parsed.json");
 
     try {
-      const statusContent = await fs.readFile(statusPath, "utf-8");
+      const attestation = await this.readAttestation(outputPath);
+      const statusContent = await this.readVerified(outputPath, "status.json", attestation);
       const status = JSON.parse(statusContent);
 
       if (!status.success) {
         throw new Error(`Benchmark failed: ${status.message}`);
       }
 
-      const benchContent = await fs.readFile(benchParsedPath, "utf-8");
-      const bench = JSON.parse(benchContent);
-      const benchParsedContent = await fs.readFile(benchParsedPath, "utf-8");
+      const benchParsedContent = await this.readVerified(outputPath, "bench_parsed.json", attestation);
+      const bench = JSON.parse(benchParsedContent);
       const benchRaw = JSON.parse(benchParsedContent);
 
       return {
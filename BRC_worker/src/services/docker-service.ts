@@ -1,7 +1,12 @@
 import { promisify } from "util";
 import { exec as execCallback } from "child_process";
+import * as fs from "fs/promises";
+import * as os from "os";
 import * as path from "path";
-import { BASE_DIR } from "../config/app-config.js";
+import { ATTESTATION_KEY, BASE_DIR } from "../config/app-config.js";
+
+// Where the daemon looks for its attestation key, which it deletes on start
+const DAEMON_KEY_FILE = "/tmp/brc-attestation.key";
 
 const exec = promisify(execCallback);
 
@@ -24,8 +29,14 @@ export class DockerService {
     folderPath: string,
     level: string
   ): Promise<void> {
+    // The key goes in between creating the container and starting it, so
+    // it's there before the daemon looks and never in the image
+    await exec(
+      `cd ${folderPath} && LEVEL=${level} CONTAINER_NAME=${containerName} docker-compose up --no-start`
+    );
+    await this.copyAttestationKey(folderPath, containerName);
     await exec(
-      `cd ${folderPath} && LEVEL=${level} CONTAINER_NAME=${containerName} docker-compose up -d`
+      `cd ${folderPath} && LEVEL=${level} CONTAINER_NAME=${containerName} docker-compose start`
     );
 
     const { stdout: containerStatus } = await exec(
@@ -54,6 +65,29 @@ export class DockerService {
     await this.checkContainerExit(folderPath, containerName);
   }
 
+  private async copyAttestationKey(
+    folderPath: string,
+    containerName: string
+  ): Promise<void> {
+    if (!ATTESTATION_KEY) {
+      throw new Error("ATTESTATION_KEY is not set, results couldn't be verified");
+    }
+
+    const { stdout: containerIds } = await exec(
+      `cd ${folderPath} && CONTAINER_NAME=${containerName} docker-compose ps -a -q`
+    );
+    const keyDir = await fs.mkdtemp(path.join(os.tmpdir(), "brc-key-"));
+    const keyPath = path.join(keyDir, "attestation.key");
+    try {
+      await fs.writeFile(keyPath, ATTESTATION_KEY, { mode: 0o600 });
+      for (const containerId of containerIds.trim().split("\n").filter(Boolean)) {
+        await exec(`docker cp ${keyPath} ${containerId}:${DAEMON_KEY_FILE}`);
+      }
+    } finally {
+      await fs.rm(keyDir, { recursive: true, force: true });
+    }
+  }
+
   async checkContainerExit(
     folderPath: string,
     containerName: string